@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use crate::process_entry::{ProcessAction, ProcessEntry};
+
+pub struct Publication {
+    pub topic: String,
+    pub payload: String,
+    pub qos: i32,
+    pub retain: bool,
+}
+
+impl Publication {
+    fn new(action: &ProcessAction, payload: &str) -> Self {
+        Self {
+            topic: action.topic.clone(),
+            payload: payload.to_string(),
+            qos: action.qos,
+            retain: action.retain,
+        }
+    }
+}
+
+struct TopicState {
+    // Action of the entry that currently owns the topic, its value was published last and its
+    // off value is used to turn the topic off again.
+    owner: ProcessAction,
+}
+
+// Decides what each topic should show when several entries publish to it.
+// The running entry with the highest priority owns a topic, earlier entries win ties.
+pub struct TopicArbiter {
+    topics: HashMap<String, TopicState>,
+}
+
+impl TopicArbiter {
+    pub fn new() -> Self {
+        Self {
+            topics: HashMap::new(),
+        }
+    }
+
+    // Returns the messages needed to bring every topic in line with its current owner.
+    pub fn resolve(&mut self, entries: &[ProcessEntry]) -> Vec<Publication> {
+        let winners = winning_actions(entries);
+        let mut publications = vec![];
+
+        for (topic, action) in &winners {
+            // A changed qos or retain flag is published again too, or a stale retained value
+            // could stay on the broker.
+            let state = self.topics.get(*topic);
+            if state.is_none_or(|state| state.owner != **action) {
+                publications.push(Publication::new(action, &action.value));
+            }
+            self.topics.insert(
+                topic.to_string(),
+                TopicState {
+                    owner: (*action).clone(),
+                },
+            );
+        }
+
+        self.topics.retain(|topic, state| {
+            if winners.contains_key(topic.as_str()) {
+                return true;
+            }
+            publications.push(Publication::new(&state.owner, &state.owner.off_value));
+            false
+        });
+        publications
+    }
+
+    // Forgets what was published and returns the full state of every topic.
+    pub fn resync(&mut self, entries: &[ProcessEntry]) -> Vec<Publication> {
+        self.topics.clear();
+        let mut publications = self.resolve(entries);
+        let mut stopped: HashMap<&str, &ProcessAction> = HashMap::new();
+        for action in sorted_by_priority(entries).flat_map(|entry| &entry.actions) {
+            if !action.topic.is_empty() && !self.topics.contains_key(&action.topic) {
+                stopped.entry(&action.topic).or_insert(action);
+            }
+        }
+        publications.extend(
+            stopped
+                .into_values()
+                .map(|action| Publication::new(action, &action.off_value)),
+        );
+        publications
+    }
+}
+
+// The action of the highest priority running entry for every topic.
+pub fn winning_actions(entries: &[ProcessEntry]) -> HashMap<&str, &ProcessAction> {
+    let mut winners: HashMap<&str, &ProcessAction> = HashMap::new();
+    for entry in sorted_by_priority(entries).filter(|entry| entry.is_running) {
+        for action in entry
+            .actions
+            .iter()
+            .filter(|action| !action.topic.is_empty())
+        {
+            winners.entry(&action.topic).or_insert(action);
+        }
+    }
+    winners
+}
+
+fn sorted_by_priority(entries: &[ProcessEntry]) -> impl Iterator<Item = &ProcessEntry> {
+    let mut sorted: Vec<&ProcessEntry> = entries.iter().collect();
+    // Stable, so entries with the same priority keep their order.
+    sorted.sort_by_key(|entry| std::cmp::Reverse(entry.priority));
+    sorted.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(priority: i32, topic: &str, value: &str, off_value: &str) -> ProcessEntry {
+        let mut entry = ProcessEntry::new();
+        entry.priority = priority;
+        entry.actions = vec![ProcessAction {
+            topic: topic.to_string(),
+            value: value.to_string(),
+            off_value: off_value.to_string(),
+            ..ProcessAction::new()
+        }];
+        entry
+    }
+
+    fn published(publications: Vec<Publication>) -> Vec<(String, String)> {
+        let mut published: Vec<(String, String)> = publications
+            .into_iter()
+            .map(|publication| (publication.topic, publication.payload))
+            .collect();
+        published.sort();
+        published
+    }
+
+    fn message(topic: &str, payload: &str) -> (String, String) {
+        (topic.to_string(), payload.to_string())
+    }
+
+    #[test]
+    fn higher_priority_entry_takes_over_topic() {
+        let mut arbiter = TopicArbiter::new();
+        let mut entries = vec![
+            entry(0, "light", "low", "off"),
+            entry(1, "light", "high", "off"),
+        ];
+        entries[0].is_running = true;
+        assert_eq!(
+            published(arbiter.resolve(&entries)),
+            vec![message("light", "low")]
+        );
+        assert!(arbiter.resolve(&entries).is_empty());
+
+        entries[1].is_running = true;
+        assert_eq!(
+            published(arbiter.resolve(&entries)),
+            vec![message("light", "high")]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_lower_priority_entry_when_owner_stops() {
+        let mut arbiter = TopicArbiter::new();
+        let mut entries = vec![
+            entry(0, "light", "low", "off"),
+            entry(1, "light", "high", "off"),
+        ];
+        entries[0].is_running = true;
+        entries[1].is_running = true;
+        arbiter.resolve(&entries);
+
+        entries[1].is_running = false;
+        assert_eq!(
+            published(arbiter.resolve(&entries)),
+            vec![message("light", "low")]
+        );
+    }
+
+    #[test]
+    fn publishes_off_value_when_last_entry_stops() {
+        let mut arbiter = TopicArbiter::new();
+        let mut entries = vec![
+            entry(0, "light", "low", "low off"),
+            entry(1, "light", "high", "high off"),
+        ];
+        entries[1].is_running = true;
+        arbiter.resolve(&entries);
+
+        entries[1].is_running = false;
+        assert_eq!(
+            published(arbiter.resolve(&entries)),
+            vec![message("light", "high off")]
+        );
+        assert!(arbiter.resolve(&entries).is_empty());
+    }
+
+    #[test]
+    fn earlier_entry_wins_tie() {
+        let mut arbiter = TopicArbiter::new();
+        let mut entries = vec![
+            entry(0, "light", "first", "off"),
+            entry(0, "light", "second", "off"),
+        ];
+        entries[0].is_running = true;
+        entries[1].is_running = true;
+        assert_eq!(
+            published(arbiter.resolve(&entries)),
+            vec![message("light", "first")]
+        );
+    }
+
+    #[test]
+    fn resync_republishes_off_values_of_idle_topics() {
+        let mut arbiter = TopicArbiter::new();
+        let mut entries = vec![
+            entry(0, "light", "on", "off"),
+            entry(0, "fan", "fast", "still"),
+            entry(0, "", "ignored", "ignored"),
+        ];
+        entries[0].is_running = true;
+        arbiter.resolve(&entries);
+
+        assert_eq!(
+            published(arbiter.resync(&entries)),
+            vec![message("fan", "still"), message("light", "on")]
+        );
+    }
+}
@@ -0,0 +1,597 @@
+use std::{io, path::PathBuf, thread};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    engine::default_config_dir,
+    error::Error,
+    fetch_processes::ProcessSnapshotter,
+    logger::{apply_log_settings, set_log_path, CLI_LOG_FILE_NAME},
+    matcher::{MatchMode, MatchTarget, Matcher},
+    mqtt::MqttClient,
+    process_entry::{validate_entry, ProcessAction, ProcessEntry, DEFAULT_QOS, DEFAULT_RETAIN},
+    process_info::ProcessInfo,
+    storage::Storage,
+    transfer::{self, ImportMode},
+};
+
+const SUBCOMMANDS: [&str; 6] = ["entries", "profiles", "mqtt", "processes", "status", "help"];
+
+const USAGE: &str = "usage: process-color <command> [--config-dir <path>]
+
+commands:
+  entries list [--json]
+  entries add <name> [entry options]
+  entries edit <id> [--name <name>] [entry options]
+  entries remove <id>
+  entries export <file> [--with-mqtt]
+  entries import <file> [--replace] [--with-mqtt]
+  profiles list [--json]
+  profiles create <name>
+  profiles rename <name> <new name>
+  profiles delete <name>
+  profiles activate <name>
+  mqtt show [--json]
+  mqtt set [--host <host>] [--port <port>] [--username <username>] [--password-stdin]
+  mqtt test
+  processes list [--json]
+  status
+  help
+
+entry options:
+  --mode <exact|case_insensitive|glob|regex|substring>
+  --target <name|exe_path|command_line>
+  --priority <n>
+  --on-delay <seconds>
+  --off-delay <seconds>
+  --action <topic> <value> <off value>  (repeatable, replaces existing actions on edit)
+  --qos <0|1|2>                         (applies to every action)
+  --no-retain                           (applies to every action)
+
+Entries and mqtt settings apply to the active profile, mqtt settings fall back to the
+global ones for profiles without their own broker.
+--password-stdin reads the broker password from the first line of stdin, so it does not
+show up in the process list or shell history.
+Without a command the GUI is started, --headless runs the engine without a window.
+A running instance picks up changes within a second.
+On Windows cmd does not wait for the app, use `start /wait` to get the exit code.";
+
+pub fn is_cli_command(args: &[String]) -> bool {
+    args.get(1)
+        .is_some_and(|command| SUBCOMMANDS.contains(&command.as_str()))
+}
+
+// Runs a subcommand against the stored config and returns the exit code.
+pub fn run_cli(args: &[String]) -> i32 {
+    let mut args = Args::new(&args[1..]);
+    let result = run_command(&mut args);
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        return 1;
+    }
+    0
+}
+
+fn run_command(args: &mut Args) -> Result<(), Error> {
+    let config_dir = args.option("--config-dir")?.map(PathBuf::from);
+    let command = args.positional();
+    let subcommand = args.positional();
+    let mut storage = open_storage(config_dir.clone())?;
+    let result = match (command.as_deref(), subcommand.as_deref()) {
+        (Some("entries"), Some("list")) => {
+            let json = args.flag("--json");
+            args.finish()?;
+            list_entries(&mut storage, json)
+        }
+        (Some("entries"), Some("add")) => {
+            let options = EntryOptions::parse(args)?;
+            let name = args.required_positional("name")?;
+            args.finish()?;
+            let mut entry = ProcessEntry::new();
+            entry.name = name;
+            options.apply(&mut entry);
+            validate_entry(&entry.name, entry.match_mode, &entry.actions)?;
+
+            let mut process_entrys = storage.get_saved_process_entrys();
+            let id = entry.id.clone();
+            process_entrys.push(entry);
+            storage.save_process_entrys(&process_entrys)?;
+            println!("{}", id);
+            Ok(())
+        }
+        (Some("entries"), Some("edit")) => {
+            let name = args.option("--name")?;
+            let options = EntryOptions::parse(args)?;
+            let id = args.required_positional("id")?;
+            args.finish()?;
+
+            let mut process_entrys = storage.get_saved_process_entrys();
+            let entry = find_entry(&mut process_entrys, &id)?;
+            if let Some(name) = name {
+                entry.name = name;
+            }
+            options.apply(entry);
+            validate_entry(&entry.name, entry.match_mode, &entry.actions)?;
+            storage.save_process_entrys(&process_entrys)
+        }
+        (Some("entries"), Some("remove")) => {
+            let id = args.required_positional("id")?;
+            args.finish()?;
+            let mut process_entrys = storage.get_saved_process_entrys();
+            find_entry(&mut process_entrys, &id)?;
+            process_entrys.retain(|entry| entry.id != id);
+            storage.save_process_entrys(&process_entrys)
+        }
+        (Some("entries"), Some("export")) => {
+            let with_mqtt_settings = args.flag("--with-mqtt");
+            let path = args.required_positional("file")?;
+            args.finish()?;
+            let mqtt_settings = with_mqtt_settings.then(|| storage.get_active_mqtt_settings());
+            transfer::export(
+                &path,
+                &storage.get_saved_process_entrys(),
+                mqtt_settings.as_ref(),
+            )
+        }
+        (Some("entries"), Some("import")) => {
+            let mode = if args.flag("--replace") {
+                ImportMode::Replace
+            } else {
+                ImportMode::Merge
+            };
+            let with_mqtt_settings = args.flag("--with-mqtt");
+            let path = args.required_positional("file")?;
+            args.finish()?;
+            let mqtt_settings = storage.get_active_mqtt_settings();
+            let import = transfer::import(
+                &path,
+                mode,
+                with_mqtt_settings,
+                &storage.get_saved_process_entrys(),
+                Some(&mqtt_settings),
+            )?;
+            storage.save_process_entrys(&import.process_entrys)?;
+            if let Some(settings) = &import.mqtt_settings {
+                storage.save_mqtt_settings(settings)?;
+            }
+            println!("added {} entries", import.summary.added);
+            for name in import.summary.skipped {
+                println!("skipped duplicate {}", name);
+            }
+            Ok(())
+        }
+        (Some("profiles"), Some("list")) => {
+            let json = args.flag("--json");
+            args.finish()?;
+            list_profiles(&mut storage, json)
+        }
+        (Some("profiles"), Some("create")) => {
+            let name = args.required_positional("name")?;
+            args.finish()?;
+            storage.change_profiles(|profiles| profiles.create(&name))
+        }
+        (Some("profiles"), Some("rename")) => {
+            let name = args.required_positional("name")?;
+            let new_name = args.required_positional("new name")?;
+            args.finish()?;
+            storage.change_profiles(|profiles| profiles.rename(&name, &new_name))
+        }
+        (Some("profiles"), Some("delete")) => {
+            let name = args.required_positional("name")?;
+            args.finish()?;
+            storage.change_profiles(|profiles| profiles.delete(&name))
+        }
+        (Some("profiles"), Some("activate")) => {
+            let name = args.required_positional("name")?;
+            args.finish()?;
+            storage.change_profiles(|profiles| profiles.activate(&name))
+        }
+        (Some("mqtt"), Some("show")) => {
+            let json = args.flag("--json");
+            args.finish()?;
+            show_mqtt(&mut storage, json)
+        }
+        (Some("mqtt"), Some("set")) => {
+            let host = args.option("--host")?;
+            let port = args
+                .option("--port")?
+                .map(|port| parse_number(&port, "port"))
+                .transpose()?;
+            let username = args.option("--username")?;
+            let password_stdin = args.flag("--password-stdin");
+            args.finish()?;
+            let password = password_stdin.then(read_password).transpose()?;
+
+            let mut settings = storage.get_active_mqtt_settings();
+            if let Some(host) = host {
+                settings.ip = host;
+            }
+            if let Some(port) = port {
+                settings.port = port;
+            }
+            // An empty value clears the credential, like an empty field in the settings page.
+            if let Some(username) = username {
+                settings.username = Some(username).filter(|username| !username.is_empty());
+            }
+            if let Some(password) = password {
+                settings.password = Some(password).filter(|password| !password.is_empty());
+            }
+            storage.save_mqtt_settings(&settings)
+        }
+        (Some("mqtt"), Some("test")) => {
+            args.finish()?;
+            test_mqtt(&mut storage)?;
+            println!("connected");
+            Ok(())
+        }
+        (Some("processes"), Some("list")) => {
+            let json = args.flag("--json");
+            args.finish()?;
+            list_processes(json)
+        }
+        (Some("status"), None) => {
+            args.finish()?;
+            status(&mut storage, config_dir.clone())
+        }
+        (Some("help"), None) => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(Error::InvalidInput(USAGE.to_string())),
+    };
+    for recovery in storage.take_recoveries() {
+        eprintln!("warning: {}", recovery);
+    }
+    result
+}
+
+fn open_storage(config_dir: Option<PathBuf>) -> Result<Storage, Error> {
+    let path = config_dir
+        .or_else(default_config_dir)
+        .and_then(|path| path.into_os_string().into_string().ok());
+    if path.is_none() {
+        return Err(Error::NotConfigured("failed to determine config directory"));
+    }
+    let path = path.unwrap();
+    let mut storage = Storage::new();
+    storage.set_path(path.clone());
+    set_log_path(path, CLI_LOG_FILE_NAME);
+    apply_log_settings(&storage.get_log_settings());
+    Ok(storage)
+}
+
+fn find_entry<'a>(
+    process_entrys: &'a mut [ProcessEntry],
+    id: &str,
+) -> Result<&'a mut ProcessEntry, Error> {
+    process_entrys
+        .iter_mut()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| Error::NotFound(format!("unknown process entry {}", id)))
+}
+
+struct EntryOptions {
+    match_mode: Option<MatchMode>,
+    match_target: Option<MatchTarget>,
+    priority: Option<i32>,
+    on_delay: Option<u64>,
+    off_delay: Option<u64>,
+    qos: Option<i32>,
+    no_retain: bool,
+    actions: Vec<Vec<String>>,
+}
+
+impl EntryOptions {
+    fn parse(args: &mut Args) -> Result<Self, Error> {
+        Ok(Self {
+            match_mode: args
+                .option("--mode")?
+                .map(|mode| parse_enum(&mode, "match mode"))
+                .transpose()?,
+            match_target: args
+                .option("--target")?
+                .map(|target| parse_enum(&target, "match target"))
+                .transpose()?,
+            priority: args
+                .option("--priority")?
+                .map(|priority| parse_number(&priority, "priority"))
+                .transpose()?,
+            on_delay: args
+                .option("--on-delay")?
+                .map(|on_delay| parse_number(&on_delay, "on delay"))
+                .transpose()?,
+            off_delay: args
+                .option("--off-delay")?
+                .map(|off_delay| parse_number(&off_delay, "off delay"))
+                .transpose()?,
+            qos: args
+                .option("--qos")?
+                .map(|qos| parse_number(&qos, "qos"))
+                .transpose()?,
+            no_retain: args.flag("--no-retain"),
+            actions: args.repeated_option("--action", 3)?,
+        })
+    }
+
+    fn apply(self, entry: &mut ProcessEntry) {
+        if let Some(match_mode) = self.match_mode {
+            entry.match_mode = match_mode;
+        }
+        if let Some(match_target) = self.match_target {
+            entry.match_target = match_target;
+        }
+        if let Some(priority) = self.priority {
+            entry.priority = priority;
+        }
+        if let Some(on_delay) = self.on_delay {
+            entry.on_delay = on_delay;
+        }
+        if let Some(off_delay) = self.off_delay {
+            entry.off_delay = off_delay;
+        }
+        if !self.actions.is_empty() {
+            entry.actions = self
+                .actions
+                .into_iter()
+                .map(|values| ProcessAction {
+                    topic: values[0].clone(),
+                    value: values[1].clone(),
+                    off_value: values[2].clone(),
+                    qos: DEFAULT_QOS,
+                    retain: DEFAULT_RETAIN,
+                })
+                .collect();
+        }
+        // Without --action these change the existing actions.
+        for action in entry.actions.iter_mut() {
+            if let Some(qos) = self.qos {
+                action.qos = qos;
+            }
+            if self.no_retain {
+                action.retain = false;
+            }
+        }
+    }
+}
+
+fn list_entries(storage: &mut Storage, json: bool) -> Result<(), Error> {
+    let process_entrys = storage.get_saved_process_entrys();
+    if json {
+        return print_json(&process_entrys);
+    }
+    for entry in process_entrys {
+        println!(
+            "{}  {}  mode={} target={} priority={} on_delay={}s off_delay={}s",
+            entry.id,
+            entry.name,
+            enum_name(&entry.match_mode),
+            enum_name(&entry.match_target),
+            entry.priority,
+            entry.on_delay,
+            entry.off_delay
+        );
+        for action in entry.actions {
+            println!(
+                "    {} = {} / {} (qos {}{})",
+                action.topic,
+                action.value,
+                action.off_value,
+                action.qos,
+                if action.retain { ", retained" } else { "" }
+            );
+        }
+    }
+    Ok(())
+}
+
+fn list_profiles(storage: &mut Storage, json: bool) -> Result<(), Error> {
+    let profiles = storage.get_profiles();
+    if json {
+        return print_json(&profiles);
+    }
+    for profile in profiles.profiles {
+        println!(
+            "{} {}{}",
+            if profile.name == profiles.active {
+                "*"
+            } else {
+                " "
+            },
+            profile.name,
+            if profile.own_broker {
+                "  (own broker)"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+fn show_mqtt(storage: &mut Storage, json: bool) -> Result<(), Error> {
+    let mut settings = storage.get_active_mqtt_settings();
+    // Never print the stored password, only whether there is one.
+    let has_password = settings.password.take().is_some();
+    if json {
+        return print_json(&settings);
+    }
+    println!("host: {}", settings.ip);
+    println!("port: {}", settings.port);
+    println!("username: {}", settings.username.as_deref().unwrap_or("-"));
+    println!("password: {}", if has_password { "set" } else { "-" });
+    println!("tls: {}", if settings.tls.is_some() { "on" } else { "off" });
+    println!(
+        "availability topic: {}",
+        settings
+            .availability
+            .as_ref()
+            .map_or("-", |availability| availability.topic.as_str())
+    );
+    Ok(())
+}
+
+fn test_mqtt(storage: &mut Storage) -> Result<(), Error> {
+    let mut settings = storage.get_active_mqtt_settings();
+    // Publishing availability would report a running instance as offline after the test.
+    settings.availability = None;
+    let mut mqtt_client = MqttClient::new();
+    mqtt_client.settings = Some(settings);
+    let result = mqtt_client.connect_blocking();
+    mqtt_client.disconnect();
+    result
+}
+
+fn read_password() -> Result<String, Error> {
+    let mut password = String::new();
+    io::stdin()
+        .read_line(&mut password)
+        .map_err(|error| Error::InvalidInput(format!("failed to read password: {}", error)))?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn snapshot_processes() -> Vec<ProcessInfo> {
+    let mut snapshotter = ProcessSnapshotter::new();
+    snapshotter.refresh();
+    thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    let mut processes = snapshotter.refresh();
+    processes.sort_by_key(|process| (process.name.to_lowercase(), process.pid));
+    processes
+}
+
+fn list_processes(json: bool) -> Result<(), Error> {
+    let processes = snapshot_processes();
+    if json {
+        return print_json(&processes);
+    }
+    for process in processes {
+        println!(
+            "{:>7}  {:<10}  {:>5.1}%  {:>8.1} MB  {}",
+            process.pid,
+            process.user.as_deref().unwrap_or("-"),
+            process.cpu_usage,
+            process.memory as f64 / 1024.0 / 1024.0,
+            process.exe.as_deref().unwrap_or(&process.name)
+        );
+    }
+    Ok(())
+}
+
+// Whether each entry's process is present right now, the delays of a running instance are not applied.
+fn status(storage: &mut Storage, config_dir: Option<PathBuf>) -> Result<(), Error> {
+    let config_dir = config_dir.or_else(default_config_dir);
+    println!(
+        "config: {}",
+        config_dir.map_or("-".to_string(), |path| path.display().to_string())
+    );
+    let settings = storage.get_active_mqtt_settings();
+    let mqtt_state = match test_mqtt(storage) {
+        Ok(()) => "reachable".to_string(),
+        Err(error) => error.to_string(),
+    };
+    println!("mqtt: {}:{} ({})", settings.ip, settings.port, mqtt_state);
+
+    let processes = snapshot_processes();
+    for entry in storage.get_saved_process_entrys() {
+        let state = match Matcher::new(entry.match_mode, &entry.name) {
+            Ok(matcher) if entry.matches(&matcher, &processes) => "running".to_string(),
+            Ok(_) => "stopped".to_string(),
+            Err(error) => format!("invalid: {}", error),
+        };
+        println!("{}  {}  {}", entry.id, entry.name, state);
+    }
+    Ok(())
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Error> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|error| Error::InvalidInput(error.to_string()))?;
+    println!("{}", json);
+    Ok(())
+}
+
+// The snake_case name the enum uses in storage and in the frontend.
+fn enum_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn parse_enum<T: DeserializeOwned>(value: &str, what: &str) -> Result<T, Error> {
+    serde_json::from_value(Value::String(value.to_string()))
+        .map_err(|_| Error::InvalidInput(format!("invalid {} \"{}\"", what, value)))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, what: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::InvalidInput(format!("invalid {} \"{}\"", what, value)))
+}
+
+// Options are taken out by name, whatever is left over is positional or unknown.
+struct Args {
+    args: Vec<String>,
+}
+
+impl Args {
+    fn new(args: &[String]) -> Self {
+        Self {
+            args: args.to_vec(),
+        }
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        let index = self.args.iter().position(|arg| arg == name);
+        if let Some(index) = index {
+            self.args.remove(index);
+        }
+        index.is_some()
+    }
+
+    fn option(&mut self, name: &str) -> Result<Option<String>, Error> {
+        let values = self.repeated_option(name, 1)?;
+        if values.len() > 1 {
+            return Err(Error::InvalidInput(format!(
+                "{} given more than once",
+                name
+            )));
+        }
+        Ok(values.into_iter().next().map(|mut values| values.remove(0)))
+    }
+
+    fn repeated_option(&mut self, name: &str, count: usize) -> Result<Vec<Vec<String>>, Error> {
+        let mut occurrences = vec![];
+        while let Some(index) = self.args.iter().position(|arg| arg == name) {
+            if index + count >= self.args.len() {
+                return Err(Error::InvalidInput(format!(
+                    "{} expects {} value(s)",
+                    name, count
+                )));
+            }
+            let values: Vec<String> = self.args.drain(index..=index + count).skip(1).collect();
+            occurrences.push(values);
+        }
+        Ok(occurrences)
+    }
+
+    fn positional(&mut self) -> Option<String> {
+        let index = self.args.iter().position(|arg| !arg.starts_with("--"))?;
+        Some(self.args.remove(index))
+    }
+
+    fn required_positional(&mut self, what: &str) -> Result<String, Error> {
+        self.positional()
+            .ok_or_else(|| Error::InvalidInput(format!("missing {}", what)))
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        if let Some(arg) = self.args.first() {
+            return Err(Error::InvalidInput(format!(
+                "unexpected argument \"{}\"",
+                arg
+            )));
+        }
+        Ok(())
+    }
+}
@@ -1,220 +1,245 @@
-use std::{
-    collections::HashSet,
-    sync::{Arc, Mutex},
-};
-
 use tauri::{AppHandle, State};
 use tauri_plugin_autostart::ManagerExt;
 use tauri_plugin_opener::OpenerExt;
 
 use crate::{
-    log, mqtt::MqttClient, mqtt_settings::MqttSettings, process_entry::ProcessEntry,
-    storage::Storage,
+    engine::Engine,
+    error::Error,
+    logger::LogSettings,
+    matcher::{MatchMode, MatchTarget},
+    mqtt_settings::{AvailabilitySettings, MqttSettings, TlsSettings},
+    operations::{MqttConnectionUpdate, ProcessEntryUpdate},
+    pacing::ScanSettings,
+    process_entry::{ProcessAction, ProcessEntry},
+    process_info::ProcessInfo,
+    profiles::ProfileList,
+    transfer::{ImportMode, ImportSummary},
 };
 
 #[tauri::command]
-pub fn add_process_entry(
-    state: State<Arc<Mutex<Vec<ProcessEntry>>>>,
-    storage: State<Arc<Mutex<Storage>>>,
-) {
-    let process_entrys = state.lock();
-    if process_entrys.is_err() {
-        log("failed to lock process entrys");
-        return;
-    }
-    let mut process_entrys = process_entrys.unwrap();
-    let storage = storage.lock();
-    if storage.is_err() {
-        log("failed to lock storage");
-        return;
-    }
-    let storage = storage.unwrap();
-
-    process_entrys.push(ProcessEntry {
-        is_running: false,
-        name: String::new(),
-        topic: String::new(),
-        value: String::new(),
-        off_value: String::new(),
-    });
-    storage.save_process_entrys(&process_entrys);
-}
-
-#[tauri::command]
-pub fn delete_process_entry(
-    index: usize,
-    state: State<Arc<Mutex<Vec<ProcessEntry>>>>,
-    storage: State<Arc<Mutex<Storage>>>,
-) {
-    let process_entrys = state.lock();
-    if process_entrys.is_err() {
-        log("failed to lock process entrys");
-        return;
-    }
-    let mut process_entrys = process_entrys.unwrap();
-    let storage = storage.lock();
-    if storage.is_err() {
-        log("failed to lock storage");
-        return;
-    }
-    let storage = storage.unwrap();
+pub fn add_process_entry(engine: State<Engine>) -> Result<String, Error> {
+    engine.add_process_entry()
+}
+
+#[tauri::command]
+pub fn delete_process_entry(id: String, engine: State<Engine>) -> Result<(), Error> {
+    engine.delete_process_entry(&id)
+}
 
-    process_entrys.remove(index);
-    storage.save_process_entrys(&process_entrys);
+// Commands that connect to the broker wait for the first attempt, off the main thread.
+async fn blocking<T: Send + 'static>(
+    engine: &State<'_, Engine>,
+    operation: impl FnOnce(Engine) -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || operation(engine))
+        .await
+        .map_err(|error| Error::Mqtt(error.to_string()))?
 }
 
 #[tauri::command]
-pub fn mqtt_connect(state: State<Arc<Mutex<MqttClient>>>) {
-    let mqtt_client = state.lock();
-    if mqtt_client.is_err() {
-        log("failed to lock mqtt client");
-        return;
-    }
-    let mut mqtt_client = mqtt_client.unwrap();
-    mqtt_client.connect();
+pub async fn mqtt_connect(engine: State<'_, Engine>) -> Result<(), Error> {
+    blocking(&engine, |engine| engine.mqtt_connect()).await
 }
 #[tauri::command]
-pub fn get_mqtt_connection(mqtt_client: State<Arc<Mutex<MqttClient>>>) -> Option<MqttSettings> {
-    let mqtt_client = mqtt_client.lock();
-    if mqtt_client.is_err() {
-        log("failed to lock mqtt client");
-        return None;
-    }
-    let mqtt_client = mqtt_client.unwrap();
-    return mqtt_client.settings.clone();
+pub fn get_mqtt_connection(engine: State<Engine>) -> Result<Option<MqttSettings>, Error> {
+    engine.get_mqtt_connection()
 }
 
 #[tauri::command]
-pub fn save_mqtt_connection(
-    mqtt_client: State<Arc<Mutex<MqttClient>>>,
-    storage: State<Arc<Mutex<Storage>>>,
+pub async fn save_mqtt_connection(
+    engine: State<'_, Engine>,
     ip: String,
     port: u16,
-) {
-    let mqtt_client = mqtt_client.lock();
-    if mqtt_client.is_err() {
-        log("failed to lock mqtt client");
-        return;
-    }
-    let mut mqtt_client = mqtt_client.unwrap();
+    username: Option<String>,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let update = MqttConnectionUpdate {
+        ip,
+        port,
+        username,
+        password,
+    };
+    blocking(&engine, |engine| engine.save_mqtt_connection(update)).await
+}
 
-    let storage = storage.lock();
-    if storage.is_err() {
-        log("failed to lock storage");
-        return;
-    }
-    let storage = storage.unwrap();
+#[tauri::command]
+pub fn get_mqtt_tls(engine: State<Engine>) -> Result<Option<TlsSettings>, Error> {
+    engine.get_mqtt_tls()
+}
 
-    let settings = MqttSettings { ip, port };
-    storage.save_mqtt_settings(&settings);
-    mqtt_client.settings = Some(settings);
-    mqtt_client.connect();
+#[tauri::command]
+pub async fn save_mqtt_tls(
+    engine: State<'_, Engine>,
+    tls: Option<TlsSettings>,
+) -> Result<(), Error> {
+    blocking(&engine, |engine| engine.save_mqtt_tls(tls)).await
 }
 
 #[tauri::command]
-pub fn open_config(app: AppHandle, storage: State<Arc<Mutex<Storage>>>) {
-    let storage = storage.lock();
-    if storage.is_err() {
-        log("failed to lock storage");
-        return;
-    }
-    let storage = storage.unwrap();
+pub fn get_mqtt_availability(engine: State<Engine>) -> Result<Option<AvailabilitySettings>, Error> {
+    engine.get_mqtt_availability()
+}
+
+#[tauri::command]
+pub async fn save_mqtt_availability(
+    engine: State<'_, Engine>,
+    availability: Option<AvailabilitySettings>,
+) -> Result<(), Error> {
+    blocking(&engine, |engine| {
+        engine.save_mqtt_availability(availability)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn open_config(app: AppHandle, engine: State<Engine>) -> Result<(), Error> {
+    let storage = engine.storage.lock().map_err(|_| Error::Lock("storage"))?;
 
     let path = &storage.path;
     if path.is_none() {
-        return;
-    }
-    let result = app
-        .opener()
-        .open_path(path.as_ref().unwrap().display().to_string(), None::<&str>);
-    if result.is_err() {
-        log("failed to open config directory");
-        return;
+        return Err(Error::NotConfigured("storage path is not set"));
     }
+    app.opener()
+        .open_path(path.as_ref().unwrap().display().to_string(), None::<&str>)
+        .map_err(|error| Error::Opener(error.to_string()))
 }
 
 #[tauri::command]
-pub fn get_process_entrys(state: State<Arc<Mutex<Vec<ProcessEntry>>>>) -> Vec<ProcessEntry> {
-    let entries = state.lock();
-    if entries.is_err() {
-        log("failed to lock process entries");
-        return vec![];
-    }
-    let entries = entries.unwrap();
-    entries.clone()
+pub fn get_process_entrys(engine: State<Engine>) -> Result<Vec<ProcessEntry>, Error> {
+    engine.get_process_entrys()
 }
 
 #[tauri::command]
-pub fn get_processes(state: State<Arc<Mutex<HashSet<String>>>>) -> Vec<String> {
-    let processes = state.lock();
-    if processes.is_err() {
-        log("failed to lock processes");
-        return vec![];
-    }
-    let processes = processes.unwrap();
-    let mut processes = processes.clone().into_iter().collect::<Vec<String>>();
-    processes.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()));
-    processes
+pub fn get_processes(engine: State<Engine>) -> Result<Vec<String>, Error> {
+    engine.get_processes()
 }
 
 #[tauri::command]
+pub fn get_process_list(engine: State<Engine>) -> Result<Vec<ProcessInfo>, Error> {
+    engine.get_process_list()
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn update_process_entry(
-    state: State<Arc<Mutex<Vec<ProcessEntry>>>>,
-    storage: State<Arc<Mutex<Storage>>>,
-    index: usize,
+    engine: State<Engine>,
+    id: String,
     name: String,
-    topic: String,
-    value: String,
-    off_value: String,
-) {
-    let process_entrys = state.lock();
-    if process_entrys.is_err() {
-        log("failed to lock process entrys");
-        return;
-    }
-    let mut process_entrys = process_entrys.unwrap();
-    let storage = storage.lock();
-    if storage.is_err() {
-        log("failed to lock storage");
-        return;
-    }
-    let storage = storage.unwrap();
-
-    for (i, entry) in process_entrys.iter_mut().enumerate() {
-        if i == index {
-            entry.name = name.clone();
-            entry.topic = topic.clone();
-            entry.value = value.clone();
-            entry.off_value = off_value.clone();
-            break;
-        }
-    }
-    storage.save_process_entrys(&process_entrys);
+    match_mode: MatchMode,
+    match_target: MatchTarget,
+    priority: i32,
+    on_delay: u64,
+    off_delay: u64,
+    actions: Vec<ProcessAction>,
+) -> Result<(), Error> {
+    engine.update_process_entry(ProcessEntryUpdate {
+        id,
+        name,
+        match_mode,
+        match_target,
+        priority,
+        on_delay,
+        off_delay,
+        actions,
+    })
+}
+
+#[tauri::command]
+pub fn get_profiles(engine: State<Engine>) -> Result<ProfileList, Error> {
+    engine.get_profiles()
+}
+
+#[tauri::command]
+pub fn create_profile(engine: State<Engine>, name: String) -> Result<(), Error> {
+    engine.create_profile(&name)
+}
+
+#[tauri::command]
+pub fn rename_profile(engine: State<Engine>, name: String, new_name: String) -> Result<(), Error> {
+    engine.rename_profile(&name, &new_name)
+}
+
+#[tauri::command]
+pub fn delete_profile(engine: State<Engine>, name: String) -> Result<(), Error> {
+    engine.delete_profile(&name)
+}
+
+// Async so the wait for the off values runs off the main thread.
+#[tauri::command]
+pub async fn activate_profile(engine: State<'_, Engine>, name: String) -> Result<(), Error> {
+    engine.activate_profile(&name)
+}
+
+#[tauri::command]
+pub async fn set_profile_broker(
+    engine: State<'_, Engine>,
+    name: String,
+    own_broker: bool,
+) -> Result<(), Error> {
+    engine.set_profile_broker(&name, own_broker)
+}
+
+#[tauri::command]
+pub fn export_entries(
+    engine: State<Engine>,
+    path: String,
+    with_mqtt_settings: bool,
+) -> Result<(), Error> {
+    engine.export_entries(&path, with_mqtt_settings)
+}
+
+// Async because importing broker settings reconnects.
+#[tauri::command]
+pub async fn import_entries(
+    engine: State<'_, Engine>,
+    path: String,
+    mode: ImportMode,
+    with_mqtt_settings: bool,
+) -> Result<ImportSummary, Error> {
+    engine.import_entries(&path, mode, with_mqtt_settings)
+}
+
+#[tauri::command]
+pub fn get_log_settings(engine: State<Engine>) -> Result<LogSettings, Error> {
+    engine.get_log_settings()
 }
 
 #[tauri::command]
-pub fn set_autostart(app: AppHandle, enabled: bool) {
+pub fn save_log_settings(engine: State<Engine>, settings: LogSettings) -> Result<(), Error> {
+    engine.save_log_settings(settings)
+}
+
+#[tauri::command]
+pub fn get_recent_logs(engine: State<Engine>, lines: usize) -> Result<Vec<String>, Error> {
+    engine.get_recent_logs(lines)
+}
+
+#[tauri::command]
+pub fn get_scan_settings(engine: State<Engine>) -> Result<ScanSettings, Error> {
+    engine.get_scan_settings()
+}
+
+#[tauri::command]
+pub fn save_scan_settings(engine: State<Engine>, settings: ScanSettings) -> Result<(), Error> {
+    engine.save_scan_settings(settings)
+}
+
+#[tauri::command]
+pub fn set_autostart(app: AppHandle, enabled: bool) -> Result<(), Error> {
     let autostart_manager = app.autolaunch();
-    if enabled {
-        let result = autostart_manager.enable();
-        if result.is_err() {
-            log("failed to enable autostart");
-        }
+    let result = if enabled {
+        autostart_manager.enable()
     } else {
-        let result = autostart_manager.disable();
-        if result.is_err() {
-            log("failed to disable autostart");
-        }
-    }
+        autostart_manager.disable()
+    };
+    result.map_err(|error| Error::Autostart(error.to_string()))
 }
 
 #[tauri::command]
-pub fn get_autostart(app: AppHandle) -> bool {
+pub fn get_autostart(app: AppHandle) -> Result<bool, Error> {
     let autostart_manager = app.autolaunch();
-    let result = autostart_manager.is_enabled();
-    if result.is_err() {
-        log("failed to check autostart status");
-        return false;
-    }
-    result.unwrap()
+    autostart_manager
+        .is_enabled()
+        .map_err(|error| Error::Autostart(error.to_string()))
 }
@@ -0,0 +1,199 @@
+use std::{
+    sync::{
+        mpsc::{channel, RecvTimeoutError},
+        Arc,
+    },
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use notify::RecursiveMode;
+use notify_debouncer_mini::new_debouncer;
+
+use crate::{
+    engine::{Engine, EngineObserver},
+    logger::apply_log_settings,
+    mqtt_settings::MqttSettings,
+    process_entry::ProcessEntry,
+    shutdown::{is_shut_down, turn_off},
+};
+
+// Editors write a file in several steps, they are picked up together once it is quiet.
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(500);
+// How often the watcher checks for shut down while nothing changes.
+const SHUT_DOWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Picks up edits made to the config files while running, e.g. by hand or by the CLI.
+pub fn watch_config(engine: Engine, observer: Arc<dyn EngineObserver>) {
+    let config_dir = engine
+        .storage
+        .lock()
+        .ok()
+        .and_then(|storage| storage.path.clone());
+    if config_dir.is_none() {
+        warn!("no config directory to watch");
+        return;
+    }
+    let config_dir = config_dir.unwrap();
+    let (sender, receiver) = channel();
+    let debouncer = new_debouncer(DEBOUNCE_INTERVAL, sender);
+    if let Err(error) = debouncer {
+        error!("failed to watch config directory: {}", error);
+        return;
+    }
+    let mut debouncer = debouncer.unwrap();
+    let result = debouncer
+        .watcher()
+        .watch(&config_dir, RecursiveMode::NonRecursive);
+    if let Err(error) = result {
+        error!("failed to watch config directory: {}", error);
+        return;
+    }
+    loop {
+        if is_shut_down() {
+            break;
+        }
+        match receiver.recv_timeout(SHUT_DOWN_CHECK_INTERVAL) {
+            Ok(Ok(events)) => {
+                // The log file and the control socket live in the same directory.
+                let config_changed = events.iter().any(|event| {
+                    event
+                        .path
+                        .extension()
+                        .is_some_and(|extension| extension == "json")
+                });
+                if config_changed {
+                    engine.reload_changed_config(observer.as_ref());
+                }
+            }
+            Ok(Err(error)) => warn!("failed to watch config directory: {}", error),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+impl Engine {
+    fn reload_changed_config(&self, observer: &dyn EngineObserver) {
+        let storage = self.storage.lock();
+        if storage.is_err() {
+            error!("failed to lock storage");
+            return;
+        }
+        let mut storage = storage.unwrap();
+        let active_profile = storage.get_profiles().active;
+        let profiles = storage.reload_profiles();
+        let mqtt_settings = storage.reload_mqtt_settings();
+        let log_settings = storage.reload_log_settings();
+        let scan_settings = storage.reload_scan_settings();
+        let process_entrys =
+            matches!(profiles, Some(Ok(_))).then(|| storage.get_saved_process_entrys());
+        // Either file can change which broker the active profile uses.
+        let active_mqtt_settings = (matches!(profiles, Some(Ok(_)))
+            || matches!(mqtt_settings, Some(Ok(_))))
+        .then(|| storage.get_active_mqtt_settings());
+        drop(storage);
+
+        let mut reloaded = false;
+        match profiles {
+            Some(Ok(profiles)) => {
+                let switched = profiles.active != active_profile;
+                self.replace_process_entrys(process_entrys.unwrap_or_default(), switched);
+                observer.profiles_changed(&profiles);
+                reloaded = true;
+            }
+            Some(Err(error)) => {
+                let message = format!("Ignored invalid process entrys: {}", error);
+                warn!("{}", message);
+                observer.config_invalid(&message);
+            }
+            None => {}
+        }
+        if let Some(Err(error)) = mqtt_settings {
+            let message = format!("Ignored invalid mqtt settings: {}", error);
+            warn!("{}", message);
+            observer.config_invalid(&message);
+        }
+        match log_settings {
+            Some(Ok(log_settings)) => {
+                apply_log_settings(&log_settings);
+                reloaded = true;
+            }
+            Some(Err(error)) => {
+                let message = format!("Ignored invalid log settings: {}", error);
+                warn!("{}", message);
+                observer.config_invalid(&message);
+            }
+            None => {}
+        }
+        match scan_settings {
+            Some(Ok(scan_settings)) => {
+                self.pacer.apply(&scan_settings);
+                reloaded = true;
+            }
+            Some(Err(error)) => {
+                let message = format!("Ignored invalid scan settings: {}", error);
+                warn!("{}", message);
+                observer.config_invalid(&message);
+            }
+            None => {}
+        }
+        if let Some(mqtt_settings) = active_mqtt_settings {
+            self.replace_mqtt_settings(mqtt_settings);
+            reloaded = true;
+        }
+        if reloaded {
+            info!("reloaded changed config");
+            observer.config_reloaded();
+        }
+    }
+
+    // Entries that are kept keep their running state, so nothing is published for them.
+    // When another profile was activated the old entries are turned off like on a switch
+    // through the app.
+    fn replace_process_entrys(&self, reloaded: Vec<ProcessEntry>, switched: bool) {
+        let process_entrys = self.process_entrys.lock();
+        if process_entrys.is_err() {
+            error!("failed to lock process entrys");
+            return;
+        }
+        let mut process_entrys = process_entrys.unwrap();
+        if switched {
+            let mqtt_client = self.mqtt_client.lock();
+            if mqtt_client.is_err() {
+                error!("failed to lock mqtt client");
+                return;
+            }
+            let mqtt_client = mqtt_client.unwrap();
+            turn_off(&mut process_entrys, &mqtt_client);
+            mqtt_client.request_resync();
+        }
+        let reloaded = reloaded
+            .into_iter()
+            .map(|mut entry| {
+                let current = process_entrys.iter().find(|current| current.id == entry.id);
+                entry.is_running = current.is_some_and(|current| current.is_running);
+                entry.pending_since = current.and_then(|current| current.pending_since);
+                entry
+            })
+            .collect();
+        *process_entrys = reloaded;
+    }
+
+    // Only reconnects when the broker settings actually changed.
+    fn replace_mqtt_settings(&self, settings: MqttSettings) {
+        let mqtt_client = self.mqtt_client.lock();
+        if mqtt_client.is_err() {
+            error!("failed to lock mqtt client");
+            return;
+        }
+        let mut mqtt_client = mqtt_client.unwrap();
+        if mqtt_client.settings.as_ref() == Some(&settings) {
+            return;
+        }
+        mqtt_client.settings = Some(settings);
+        if let Err(error) = mqtt_client.connect() {
+            warn!("{}", error);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use windows_sys::Win32::System::Console::{AllocConsole, AttachConsole, ATTACH_PARENT_PROCESS};
+
+// Release builds use the windows subsystem and start without a console, so output and Ctrl+C
+// only work after attaching to the console of the shell that started us. Without one a new
+// console is created when `allocate` is set.
+pub fn attach_console(allocate: bool) {
+    let attached = unsafe { AttachConsole(ATTACH_PARENT_PROCESS) } != 0;
+    if !attached && allocate {
+        unsafe { AllocConsole() };
+    }
+}
@@ -0,0 +1,513 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{remove_file, set_permissions, Permissions},
+    io::{BufRead, BufReader, Write},
+    os::{
+        fd::AsRawFd,
+        unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use log::{debug, error, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    engine::{Engine, EngineObserver},
+    error::Error,
+    logger::LogSettings,
+    mqtt_settings::{AvailabilitySettings, TlsSettings},
+    pacing::ScanSettings,
+    process_entry::RunningState,
+    profiles::ProfileList,
+    transfer::ImportMode,
+};
+
+const SOCKET_NAME: &str = "control.sock";
+// A client that stops reading must not stall the monitor loop that sends the notifications.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const EVENTS: [&str; 7] = [
+    "running_states",
+    "mqtt_connection_state",
+    "mqtt_connection_failed",
+    "storage_recovered",
+    "config_reloaded",
+    "config_invalid",
+    "profiles_changed",
+];
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const SERVER_ERROR: i32 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct NameParams {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RenameParams {
+    name: String,
+    new_name: String,
+}
+
+#[derive(Deserialize)]
+struct ProfileBrokerParams {
+    name: String,
+    own_broker: bool,
+}
+
+#[derive(Deserialize)]
+struct ExportParams {
+    path: String,
+    #[serde(default)]
+    with_mqtt_settings: bool,
+}
+
+#[derive(Deserialize)]
+struct ImportParams {
+    path: String,
+    mode: ImportMode,
+    #[serde(default)]
+    with_mqtt_settings: bool,
+}
+
+#[derive(Deserialize)]
+struct LogSettingsParams {
+    settings: LogSettings,
+}
+
+#[derive(Deserialize)]
+struct ScanSettingsParams {
+    settings: ScanSettings,
+}
+
+#[derive(Deserialize)]
+struct LogLinesParams {
+    lines: usize,
+}
+
+#[derive(Deserialize)]
+struct TlsParams {
+    tls: Option<TlsSettings>,
+}
+
+#[derive(Deserialize)]
+struct AvailabilityParams {
+    availability: Option<AvailabilitySettings>,
+}
+
+#[derive(Deserialize)]
+struct SubscribeParams {
+    events: Option<Vec<String>>,
+}
+
+struct RpcError {
+    code: i32,
+    message: String,
+    data: Option<Value>,
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        Self {
+            code: SERVER_ERROR,
+            message: error.to_string(),
+            data: Some(json!({ "code": error.code() })),
+        }
+    }
+}
+
+impl RpcError {
+    fn new(code: i32, message: String) -> Self {
+        Self {
+            code,
+            message,
+            data: None,
+        }
+    }
+}
+
+struct Subscriber {
+    events: HashSet<String>,
+    stream: Arc<Mutex<UnixStream>>,
+}
+
+// Line delimited JSON-RPC 2.0 on a unix socket in the config directory, readable only by
+// the current user. Offers the same operations as the Tauri commands and lets clients
+// subscribe to `running_states`, `mqtt_connection_state`, `mqtt_connection_failed`,
+// `storage_recovered`, `config_reloaded`, `config_invalid` and `profiles_changed` notifications.
+pub struct ControlSocket {
+    path: PathBuf,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl ControlSocket {
+    pub fn start(engine: &Engine, config_dir: &Path) -> Option<Arc<Self>> {
+        let path = config_dir.join(SOCKET_NAME);
+        if path.exists() {
+            // A socket file nobody listens on is left over from an instance that did not exit cleanly.
+            if UnixStream::connect(&path).is_ok() {
+                warn!("control socket is already in use by another instance");
+                return None;
+            }
+            if remove_file(&path).is_err() {
+                error!("failed to remove stale control socket");
+                return None;
+            }
+        }
+        // The socket is created with the umask applied, a permissive one would let other users
+        // connect before the permissions below are set.
+        let umask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(&path);
+        unsafe { libc::umask(umask) };
+        if listener.is_err() {
+            error!("failed to bind control socket");
+            return None;
+        }
+        let listener = listener.unwrap();
+        if set_permissions(&path, Permissions::from_mode(0o600)).is_err() {
+            error!("failed to restrict control socket permissions");
+            let _ = remove_file(&path);
+            return None;
+        }
+
+        let control_socket = Arc::new(Self {
+            path,
+            subscribers: Mutex::new(vec![]),
+        });
+        let accept_control_socket = control_socket.clone();
+        let engine = engine.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stream.is_err() {
+                    error!("failed to accept control socket connection");
+                    continue;
+                }
+                let stream = stream.unwrap();
+                let uid = peer_uid(&stream);
+                if uid != Some(unsafe { libc::getuid() }) {
+                    warn!("rejected control socket client of another user");
+                    continue;
+                }
+                if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                    error!("failed to set control socket write timeout");
+                    continue;
+                }
+                debug!("control socket client connected");
+                let control_socket = accept_control_socket.clone();
+                let engine = engine.clone();
+                thread::spawn(move || control_socket.handle_connection(&engine, stream));
+            }
+        });
+        Some(control_socket)
+    }
+
+    fn handle_connection(&self, engine: &Engine, stream: UnixStream) {
+        let reader = stream.try_clone();
+        if reader.is_err() {
+            error!("failed to clone control socket connection");
+            return;
+        }
+        let reader = BufReader::new(reader.unwrap());
+        let stream = Arc::new(Mutex::new(stream));
+        for line in reader.lines() {
+            if line.is_err() {
+                break;
+            }
+            let line = line.unwrap();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = match parse_request(&line) {
+                Ok(request) => request,
+                Err((id, error)) => {
+                    if !write_line(&stream, &response(id, Err(error))) {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let result = if request.method == "subscribe" {
+                self.subscribe(&stream, request.params)
+            } else {
+                call(engine, &request.method, request.params)
+            };
+            // Requests without an id are notifications and get no response.
+            if let Some(id) = request.id {
+                if !write_line(&stream, &response(id, result)) {
+                    break;
+                }
+            }
+        }
+        self.unsubscribe(&stream);
+    }
+
+    fn subscribe(&self, stream: &Arc<Mutex<UnixStream>>, params: Value) -> Result<Value, RpcError> {
+        // Without params every event is subscribed.
+        let events = if params.is_null() {
+            None
+        } else {
+            parse_params::<SubscribeParams>(params)?.events
+        };
+        let events: HashSet<String> = match events {
+            Some(events) => events.into_iter().collect(),
+            None => EVENTS.iter().map(|event| event.to_string()).collect(),
+        };
+        if let Some(event) = events
+            .iter()
+            .find(|event| !EVENTS.contains(&event.as_str()))
+        {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("unknown event \"{}\"", event),
+            ));
+        }
+        let subscribers = self.subscribers.lock();
+        if subscribers.is_err() {
+            return Err(Error::Lock("control socket subscribers").into());
+        }
+        let mut subscribers = subscribers.unwrap();
+        subscribers.retain(|subscriber| !Arc::ptr_eq(&subscriber.stream, stream));
+        subscribers.push(Subscriber {
+            events,
+            stream: stream.clone(),
+        });
+        Ok(Value::Bool(true))
+    }
+
+    fn unsubscribe(&self, stream: &Arc<Mutex<UnixStream>>) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|subscriber| !Arc::ptr_eq(&subscriber.stream, stream));
+        }
+    }
+
+    fn notify<T: Serialize>(&self, event: &str, params: &T) {
+        let subscribers = self.subscribers.lock();
+        if subscribers.is_err() {
+            error!("failed to lock control socket subscribers");
+            return;
+        }
+        let notification = json!({ "jsonrpc": "2.0", "method": event, "params": params });
+        // Subscribers whose connection is gone are dropped.
+        subscribers.unwrap().retain(|subscriber| {
+            !subscriber.events.contains(event) || write_line(&subscriber.stream, &notification)
+        });
+    }
+}
+
+impl EngineObserver for ControlSocket {
+    fn running_states(&self, running_states: &HashMap<String, RunningState>) {
+        self.notify("running_states", running_states);
+    }
+
+    fn mqtt_connection_state(&self, connected: bool) {
+        self.notify("mqtt_connection_state", &connected);
+    }
+
+    fn mqtt_connection_failed(&self, message: &str) {
+        self.notify("mqtt_connection_failed", &message);
+    }
+
+    fn storage_recovered(&self, message: &str) {
+        self.notify("storage_recovered", &message);
+    }
+
+    fn config_reloaded(&self) {
+        self.notify("config_reloaded", &Value::Null);
+    }
+
+    fn config_invalid(&self, message: &str) {
+        self.notify("config_invalid", &message);
+    }
+
+    fn profiles_changed(&self, profiles: &ProfileList) {
+        self.notify("profiles_changed", profiles);
+    }
+
+    // The next instance would otherwise find a socket file nobody listens on.
+    fn shut_down(&self) {
+        if self.path.exists() && remove_file(&self.path).is_err() {
+            error!("failed to remove control socket");
+        }
+    }
+}
+
+// Invalid JSON is a parse error, anything else that is not a JSON-RPC 2.0 request an
+// invalid request, answered with its id if it has one.
+fn parse_request(line: &str) -> Result<Request, (Value, RpcError)> {
+    let value = serde_json::from_str::<Value>(line)
+        .map_err(|error| (Value::Null, RpcError::new(PARSE_ERROR, error.to_string())))?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let request = serde_json::from_value::<Request>(value).map_err(|error| {
+        (
+            id.clone(),
+            RpcError::new(INVALID_REQUEST, error.to_string()),
+        )
+    })?;
+    if request.jsonrpc != "2.0" {
+        return Err((
+            id,
+            RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"".to_string()),
+        ));
+    }
+    Ok(request)
+}
+
+fn call(engine: &Engine, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "get_process_entrys" => to_value(engine.get_process_entrys()),
+        "add_process_entry" => to_value(engine.add_process_entry()),
+        "update_process_entry" => to_value(engine.update_process_entry(parse_params(params)?)),
+        "delete_process_entry" => {
+            let params: IdParams = parse_params(params)?;
+            to_value(engine.delete_process_entry(&params.id))
+        }
+        "get_processes" => to_value(engine.get_processes()),
+        "get_process_list" => to_value(engine.get_process_list()),
+        "mqtt_connect" => to_value(engine.mqtt_connect()),
+        "get_mqtt_connection_state" => to_value(engine.is_mqtt_connected()),
+        "get_mqtt_connection" => to_value(engine.get_mqtt_connection()),
+        "save_mqtt_connection" => to_value(engine.save_mqtt_connection(parse_params(params)?)),
+        "get_mqtt_tls" => to_value(engine.get_mqtt_tls()),
+        "save_mqtt_tls" => {
+            let params: TlsParams = parse_params(params)?;
+            to_value(engine.save_mqtt_tls(params.tls))
+        }
+        "get_mqtt_availability" => to_value(engine.get_mqtt_availability()),
+        "save_mqtt_availability" => {
+            let params: AvailabilityParams = parse_params(params)?;
+            to_value(engine.save_mqtt_availability(params.availability))
+        }
+        "get_profiles" => to_value(engine.get_profiles()),
+        "create_profile" => {
+            let params: NameParams = parse_params(params)?;
+            to_value(engine.create_profile(&params.name))
+        }
+        "rename_profile" => {
+            let params: RenameParams = parse_params(params)?;
+            to_value(engine.rename_profile(&params.name, &params.new_name))
+        }
+        "delete_profile" => {
+            let params: NameParams = parse_params(params)?;
+            to_value(engine.delete_profile(&params.name))
+        }
+        "activate_profile" => {
+            let params: NameParams = parse_params(params)?;
+            to_value(engine.activate_profile(&params.name))
+        }
+        "set_profile_broker" => {
+            let params: ProfileBrokerParams = parse_params(params)?;
+            to_value(engine.set_profile_broker(&params.name, params.own_broker))
+        }
+        "export_entries" => {
+            let params: ExportParams = parse_params(params)?;
+            to_value(engine.export_entries(&params.path, params.with_mqtt_settings))
+        }
+        "import_entries" => {
+            let params: ImportParams = parse_params(params)?;
+            to_value(engine.import_entries(&params.path, params.mode, params.with_mqtt_settings))
+        }
+        "get_log_settings" => to_value(engine.get_log_settings()),
+        "save_log_settings" => {
+            let params: LogSettingsParams = parse_params(params)?;
+            to_value(engine.save_log_settings(params.settings))
+        }
+        "get_scan_settings" => to_value(engine.get_scan_settings()),
+        "save_scan_settings" => {
+            let params: ScanSettingsParams = parse_params(params)?;
+            to_value(engine.save_scan_settings(params.settings))
+        }
+        "get_recent_logs" => {
+            let params: LogLinesParams = parse_params(params)?;
+            to_value(engine.get_recent_logs(params.lines))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method \"{}\"", method),
+        )),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|error| RpcError::new(INVALID_PARAMS, error.to_string()))
+}
+
+fn to_value<T: Serialize>(result: Result<T, Error>) -> Result<Value, RpcError> {
+    let value = result?;
+    serde_json::to_value(value).map_err(|error| RpcError::new(SERVER_ERROR, error.to_string()))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => {
+            let mut body = json!({ "code": error.code, "message": error.message });
+            if let Some(data) = error.data {
+                body["data"] = data;
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": body })
+        }
+    }
+}
+
+fn write_line(stream: &Arc<Mutex<UnixStream>>, value: &Value) -> bool {
+    let stream = stream.lock();
+    if stream.is_err() {
+        return false;
+    }
+    let mut stream = stream.unwrap();
+    writeln!(stream, "{}", value).is_ok()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    (result == 0).then_some(credentials.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    let mut uid = 0;
+    let mut gid = 0;
+    let result = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    (result == 0).then_some(uid)
+}
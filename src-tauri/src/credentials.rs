@@ -0,0 +1,72 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+use log::error;
+
+// Not encryption: the key ships with the binary. This only keeps secrets from
+// being readable in plain text when looking at the stored files.
+const KEY: &[u8] = b"process-color/credentials";
+const SALT_LENGTH: usize = 8;
+
+pub fn obfuscate(secret: &str) -> Vec<u8> {
+    let salt = RandomState::new().build_hasher().finish().to_le_bytes();
+    let mut data = salt.to_vec();
+    data.extend(apply_keystream(&salt, secret.as_bytes()));
+    data
+}
+
+pub fn reveal(data: &[u8]) -> Option<String> {
+    if data.len() < SALT_LENGTH {
+        error!("failed to reveal stored secret");
+        return None;
+    }
+    let (salt, secret) = data.split_at(SALT_LENGTH);
+    let revealed = String::from_utf8(apply_keystream(salt, secret));
+    if revealed.is_err() {
+        error!("failed to reveal stored secret");
+        return None;
+    }
+    Some(revealed.unwrap())
+}
+
+fn apply_keystream(salt: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut state: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in KEY.iter().chain(salt) {
+        state ^= *byte as u64;
+        state = state.wrapping_mul(0x0100_0000_01b3);
+    }
+    data.iter()
+        .map(|byte| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            byte ^ (state as u8)
+        })
+        .collect()
+}
+
+// Hex form of `obfuscate` for storing the secret in a text file.
+pub fn obfuscate_hex(secret: &str) -> String {
+    obfuscate(secret)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn reveal_hex(data: &str) -> Option<String> {
+    if !data.len().is_multiple_of(2) || !data.is_ascii() {
+        error!("failed to reveal stored secret");
+        return None;
+    }
+    let bytes = (0..data.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&data[index..index + 2], 16))
+        .collect::<Result<Vec<u8>, _>>();
+    if bytes.is_err() {
+        error!("failed to reveal stored secret");
+        return None;
+    }
+    reveal(&bytes.unwrap())
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc, Mutex, OnceLock},
+    thread,
+    time::Instant,
+};
+
+use log::{debug, error, info, warn};
+
+use crate::{
+    arbiter::TopicArbiter,
+    config_watcher::watch_config,
+    fetch_processes::{fetch_processes, ChangeSignal},
+    logger::{apply_log_settings, set_log_path, LOG_FILE_NAME},
+    matcher::MatcherCache,
+    mqtt::MqttClient,
+    pacing::Pacer,
+    process_entry::{ProcessEntry, RunningState},
+    process_info::ProcessInfo,
+    profiles::ProfileList,
+    shutdown::{is_shut_down, shutdown},
+    storage::Storage,
+};
+
+// Same as the identifier in tauri.conf.json, Tauri derives the app data directory from it.
+const IDENTIFIER: &str = "com.process-color.app";
+
+// The directory the GUI stores its config in, used when running without Tauri.
+pub fn default_config_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(IDENTIFIER))
+}
+
+// Receives the state of the engine, the GUI forwards it to the webview.
+pub trait EngineObserver: Send + Sync + 'static {
+    fn running_states(&self, _running_states: &HashMap<String, RunningState>) {}
+    fn mqtt_connection_state(&self, _connected: bool) {}
+    // Connecting to the broker failed, it is retried in the background.
+    fn mqtt_connection_failed(&self, _message: &str) {}
+    // A stored file could not be read and was restored from a backup or reset.
+    fn storage_recovered(&self, _message: &str) {}
+    // The config files were changed on disk and the engine picked the changes up.
+    fn config_reloaded(&self) {}
+    // The config files were changed on disk but the new content was rejected.
+    fn config_invalid(&self, _message: &str) {}
+    // Profiles were created, renamed, deleted or another one was activated.
+    fn profiles_changed(&self, _profiles: &ProfileList) {}
+    // The engine shut down, the app is about to exit.
+    fn shut_down(&self) {}
+}
+
+impl EngineObserver for () {}
+
+impl EngineObserver for Vec<Arc<dyn EngineObserver>> {
+    fn running_states(&self, running_states: &HashMap<String, RunningState>) {
+        for observer in self {
+            observer.running_states(running_states);
+        }
+    }
+
+    fn mqtt_connection_state(&self, connected: bool) {
+        for observer in self {
+            observer.mqtt_connection_state(connected);
+        }
+    }
+
+    fn mqtt_connection_failed(&self, message: &str) {
+        for observer in self {
+            observer.mqtt_connection_failed(message);
+        }
+    }
+
+    fn storage_recovered(&self, message: &str) {
+        for observer in self {
+            observer.storage_recovered(message);
+        }
+    }
+
+    fn config_reloaded(&self) {
+        for observer in self {
+            observer.config_reloaded();
+        }
+    }
+
+    fn config_invalid(&self, message: &str) {
+        for observer in self {
+            observer.config_invalid(message);
+        }
+    }
+
+    fn profiles_changed(&self, profiles: &ProfileList) {
+        for observer in self {
+            observer.profiles_changed(profiles);
+        }
+    }
+
+    fn shut_down(&self) {
+        for observer in self {
+            observer.shut_down();
+        }
+    }
+}
+
+// Process snapshotting, entry evaluation, MQTT publishing and storage, independent of any UI.
+#[derive(Clone)]
+pub struct Engine {
+    pub storage: Arc<Mutex<Storage>>,
+    pub process_entrys: Arc<Mutex<Vec<ProcessEntry>>>,
+    pub mqtt_client: Arc<Mutex<MqttClient>>,
+    pub processes: Arc<Mutex<Vec<ProcessInfo>>>,
+    // Notified when process events changed the snapshot, wakes the monitor loop early.
+    processes_changed: Arc<ChangeSignal>,
+    // Set when the process list is read, so CPU usage and memory are refreshed with events.
+    pub processes_requested: Arc<AtomicBool>,
+    pub pacer: Arc<Pacer>,
+    // Set by `start`, operations report changes that did not come from the monitor loop to it.
+    observer: Arc<OnceLock<Arc<dyn EngineObserver>>>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            storage: Arc::new(Mutex::new(Storage::new())),
+            process_entrys: Arc::new(Mutex::new(vec![])),
+            mqtt_client: Arc::new(Mutex::new(MqttClient::new())),
+            processes: Arc::new(Mutex::new(vec![])),
+            processes_changed: Arc::new(ChangeSignal::new()),
+            processes_requested: Arc::new(AtomicBool::new(false)),
+            pacer: Arc::new(Pacer::new()),
+            observer: Arc::new(OnceLock::new()),
+        }
+    }
+
+    // Loads entries and settings from the config directory and connects to the broker.
+    pub fn load(&self, path: String) {
+        let storage = self.storage.lock();
+        if storage.is_err() {
+            error!("failed to lock storage");
+            return;
+        }
+        let mut storage = storage.unwrap();
+        storage.set_path(path.clone());
+        set_log_path(path, LOG_FILE_NAME);
+        apply_log_settings(&storage.get_log_settings());
+        self.pacer.apply(&storage.get_scan_settings());
+
+        let process_entrys = self.process_entrys.lock();
+        if process_entrys.is_err() {
+            error!("failed to lock process entrys");
+            return;
+        }
+        let mut process_entrys = process_entrys.unwrap();
+        *process_entrys = storage.get_saved_process_entrys();
+        info!(
+            "loaded {} entries of profile \"{}\"",
+            process_entrys.len(),
+            storage.get_profiles().active
+        );
+        drop(process_entrys);
+
+        let mqtt_client = self.mqtt_client.lock();
+        if mqtt_client.is_err() {
+            error!("failed to lock mqtt client");
+            return;
+        }
+        let mut mqtt_client = mqtt_client.unwrap();
+        mqtt_client.settings = Some(storage.get_active_mqtt_settings());
+        if let Err(error) = mqtt_client.connect() {
+            warn!("{}", error);
+        }
+    }
+
+    // Spawns the monitoring threads, they stop once the engine is shut down.
+    pub fn start(&self, observer: Arc<dyn EngineObserver>) {
+        if self.observer.set(observer.clone()).is_err() {
+            warn!("engine was already started");
+            return;
+        }
+        let recoveries = self
+            .storage
+            .lock()
+            .map(|mut storage| storage.take_recoveries());
+        if recoveries.is_err() {
+            error!("failed to lock storage");
+        }
+        for message in recoveries.unwrap_or_default() {
+            observer.storage_recovered(&message);
+        }
+
+        let processes = self.processes.clone();
+        let processes_changed = self.processes_changed.clone();
+        let processes_requested = self.processes_requested.clone();
+        let pacer = self.pacer.clone();
+        thread::spawn(|| fetch_processes(processes, processes_changed, processes_requested, pacer));
+
+        let engine = self.clone();
+        let config_observer = observer.clone();
+        thread::spawn(move || watch_config(engine, config_observer));
+
+        let process_entrys = self.process_entrys.clone();
+        let mqtt_client = self.mqtt_client.clone();
+        let processes = self.processes.clone();
+        let processes_changed = self.processes_changed.clone();
+        let pacer = self.pacer.clone();
+        let running_states_observer = observer.clone();
+        let mut matchers = MatcherCache::new();
+        let mut arbiter = TopicArbiter::new();
+        thread::spawn(move || loop {
+            if is_shut_down() {
+                break;
+            }
+            let running_states = {
+                let process_entrys = process_entrys.lock();
+                if process_entrys.is_err() {
+                    error!("failed to lock process entrys");
+                    continue;
+                }
+                let mut process_entrys = process_entrys.unwrap();
+
+                let mqtt_client = mqtt_client.lock();
+                if mqtt_client.is_err() {
+                    error!("failed to lock mqtt client");
+                    continue;
+                }
+                let mqtt_client = mqtt_client.unwrap();
+
+                let processes = processes.lock();
+                if processes.is_err() {
+                    error!("failed to lock processes");
+                    continue;
+                }
+                let processes = processes.unwrap();
+
+                let now = Instant::now();
+                pacer.set_has_entries(!process_entrys.is_empty());
+                for entry in process_entrys.iter_mut() {
+                    let is_present = matchers
+                        .get(entry.match_mode, &entry.name)
+                        .is_some_and(|matcher| entry.matches(matcher, &processes));
+                    let was_running = entry.is_running;
+                    let previous_state = entry.running_state();
+                    entry.observe(is_present, now);
+                    if entry.running_state() != previous_state {
+                        pacer.state_changed();
+                    }
+                    if entry.is_running != was_running {
+                        info!(
+                            "entry \"{}\" {}",
+                            entry.name,
+                            if entry.is_running {
+                                "started"
+                            } else {
+                                "stopped"
+                            }
+                        );
+                    }
+                }
+                pacer.set_any_running(process_entrys.iter().any(|entry| entry.is_running));
+
+                let publications = if mqtt_client.take_resync_request() {
+                    arbiter.resync(&process_entrys)
+                } else {
+                    arbiter.resolve(&process_entrys)
+                };
+                for publication in publications {
+                    debug!(
+                        "publishing \"{}\" to {}",
+                        publication.payload, publication.topic
+                    );
+                    mqtt_client.publish(
+                        &publication.topic,
+                        &publication.payload,
+                        publication.qos,
+                        publication.retain,
+                    );
+                }
+
+                (*process_entrys)
+                    .iter()
+                    .map(|entry| (entry.id.clone(), entry.running_state()))
+                    .collect::<HashMap<String, RunningState>>()
+            };
+            // Outside the locks, a slow observer must not hold up commands.
+            running_states_observer.running_states(&running_states);
+            // Delays are still evaluated in the interval.
+            processes_changed.wait(pacer.evaluation_interval());
+        });
+
+        let mqtt_client = self.mqtt_client.clone();
+        let pacer = self.pacer.clone();
+        thread::spawn(move || loop {
+            if is_shut_down() {
+                break;
+            }
+            let (connected, connect_error) = {
+                let mqtt_client = mqtt_client.lock();
+                if mqtt_client.is_err() {
+                    error!("failed to lock mqtt client");
+                    continue;
+                }
+                let mqtt_client = mqtt_client.unwrap();
+                (mqtt_client.is_connected(), mqtt_client.take_connect_error())
+            };
+            observer.mqtt_connection_state(connected);
+            if let Some(message) = connect_error {
+                observer.mqtt_connection_failed(&message);
+            }
+            thread::sleep(pacer.connection_state_interval());
+        });
+    }
+
+    pub fn observer(&self) -> Option<&Arc<dyn EngineObserver>> {
+        self.observer.get()
+    }
+
+    pub fn shutdown(&self) {
+        shutdown(&self.process_entrys, &self.mqtt_client);
+        if let Some(observer) = self.observer() {
+            observer.shut_down();
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+
+use paho_mqtt::ConnectReturnCode;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+// Returned by commands, the frontend receives it as { code, message }.
+#[derive(Debug)]
+pub enum Error {
+    Lock(&'static str),
+    NotFound(String),
+    InvalidInput(String),
+    NotConfigured(&'static str),
+    Storage(String),
+    MqttDns(String),
+    MqttRefused(String),
+    MqttAuth(String),
+    MqttTimeout,
+    MqttTls(String),
+    Mqtt(String),
+    Autostart(String),
+    Opener(String),
+    Transfer(String),
+}
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Lock(_) => "lock",
+            Error::NotFound(_) => "not_found",
+            Error::InvalidInput(_) => "invalid_input",
+            Error::NotConfigured(_) => "not_configured",
+            Error::Storage(_) => "storage",
+            Error::MqttDns(_) => "mqtt_dns",
+            Error::MqttRefused(_) => "mqtt_refused",
+            Error::MqttAuth(_) => "mqtt_auth",
+            Error::MqttTimeout => "mqtt_timeout",
+            Error::MqttTls(_) => "mqtt_tls",
+            Error::Mqtt(_) => "mqtt",
+            Error::Autostart(_) => "autostart",
+            Error::Opener(_) => "opener",
+            Error::Transfer(_) => "transfer",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Lock(name) => write!(f, "failed to lock {}", name),
+            Error::NotFound(message) => write!(f, "{}", message),
+            Error::InvalidInput(message) => write!(f, "{}", message),
+            Error::NotConfigured(message) => write!(f, "{}", message),
+            Error::Storage(message) => write!(f, "{}", message),
+            Error::MqttDns(host) => write!(f, "could not resolve MQTT broker host \"{}\"", host),
+            Error::MqttRefused(message) => write!(f, "MQTT broker refused connection: {}", message),
+            Error::MqttAuth(message) => write!(f, "MQTT authentication failed: {}", message),
+            Error::MqttTimeout => write!(f, "timed out connecting to MQTT broker"),
+            Error::MqttTls(message) => write!(f, "MQTT TLS error: {}", message),
+            Error::Mqtt(message) => write!(f, "MQTT error: {}", message),
+            Error::Autostart(message) => write!(f, "autostart error: {}", message),
+            Error::Opener(message) => write!(f, "failed to open path: {}", message),
+            Error::Transfer(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("Error", 2)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+impl From<paho_mqtt::Error> for Error {
+    fn from(error: paho_mqtt::Error) -> Self {
+        match error {
+            paho_mqtt::Error::ConnectReturn(
+                code
+                @ (ConnectReturnCode::BadUserNameOrPassword | ConnectReturnCode::NotAuthorized),
+            ) => Error::MqttAuth(code.to_string()),
+            paho_mqtt::Error::ConnectReturn(code) => Error::MqttRefused(code.to_string()),
+            paho_mqtt::Error::ReasonCode(code) => Error::MqttRefused(code.to_string()),
+            paho_mqtt::Error::Timeout | paho_mqtt::Error::TcpConnectTimeout => Error::MqttTimeout,
+            paho_mqtt::Error::TcpTlsConnectFailure
+            | paho_mqtt::Error::TcpConnectCompletionFailure
+            | paho_mqtt::Error::SocketError(_) => Error::MqttRefused(error.to_string()),
+            paho_mqtt::Error::SslNotSupported => Error::MqttTls(error.to_string()),
+            error => Error::Mqtt(error.to_string()),
+        }
+    }
+}
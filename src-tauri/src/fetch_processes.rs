@@ -1,36 +1,194 @@
 use std::{
-    collections::HashSet,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, RefreshKind, System};
+use log::{error, info, trace, warn};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, RefreshKind, System, UpdateKind, Users};
 
-pub fn fetch_processes(processes: Arc<Mutex<HashSet<String>>>) {
-    let mut sys = System::new_with_specifics(
-        RefreshKind::nothing().with_processes(ProcessRefreshKind::nothing()),
-    );
+use crate::{pacing::Pacer, process_info::ProcessInfo};
 
+pub struct ProcessSnapshotter {
+    sys: System,
+    users: Users,
+    refresh_kind: ProcessRefreshKind,
+}
+
+impl ProcessSnapshotter {
+    pub fn new() -> Self {
+        let refresh_kind = ProcessRefreshKind::nothing()
+            .with_exe(UpdateKind::OnlyIfNotSet)
+            .with_cmd(UpdateKind::OnlyIfNotSet)
+            .with_user(UpdateKind::OnlyIfNotSet)
+            .with_cpu()
+            .with_memory();
+        Self {
+            sys: System::new_with_specifics(RefreshKind::nothing().with_processes(refresh_kind)),
+            users: Users::new_with_refreshed_list(),
+            refresh_kind,
+        }
+    }
+
+    // CPU usage is relative to the previous refresh, so it is zero on the first one.
+    pub fn refresh(&mut self) -> Vec<ProcessInfo> {
+        self.sys
+            .refresh_processes_specifics(ProcessesToUpdate::All, true, self.refresh_kind);
+        // Only reread the user list when a process belongs to a user we have not seen yet.
+        let has_unknown_user = self.sys.processes().values().any(|process| {
+            process
+                .user_id()
+                .is_some_and(|user_id| self.users.get_user_by_id(user_id).is_none())
+        });
+        if has_unknown_user {
+            self.users.refresh();
+        }
+        self.sys
+            .processes()
+            .values()
+            .filter_map(|process| ProcessInfo::new(process, &self.users))
+            .collect()
+    }
+}
+
+// Wakes a thread waiting for the processes to change.
+pub struct ChangeSignal {
+    changed: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl ChangeSignal {
+    pub fn new() -> Self {
+        Self {
+            changed: Mutex::new(false),
+            condvar: Condvar::new(),
+        }
+    }
+
+    pub fn notify(&self) {
+        if let Ok(mut changed) = self.changed.lock() {
+            *changed = true;
+            self.condvar.notify_all();
+        }
+    }
+
+    // Returns true when notified before the timeout passed.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let changed = self.changed.lock();
+        if changed.is_err() {
+            thread::sleep(timeout);
+            return false;
+        }
+        let result = self
+            .condvar
+            .wait_timeout_while(changed.unwrap(), timeout, |changed| !*changed);
+        if result.is_err() {
+            return false;
+        }
+        let (mut changed, _) = result.unwrap();
+        let notified = *changed;
+        *changed = false;
+        notified
+    }
+}
+
+// Refreshes the snapshot of all processes. On Linux process events from the kernel trigger a
+// refresh right away and `processes_changed` is notified, so entries react without waiting
+// for the next poll. Without events every process is rescanned in the scan interval.
+// With events CPU usage and memory are only refreshed while `processes_requested` is set by
+// someone reading the process list.
+pub fn fetch_processes(
+    processes: Arc<Mutex<Vec<ProcessInfo>>>,
+    processes_changed: Arc<ChangeSignal>,
+    processes_requested: Arc<AtomicBool>,
+    pacer: Arc<Pacer>,
+) {
+    let mut process_events = ProcessEvents::open();
+    let mut snapshotter = ProcessSnapshotter::new();
+    store_snapshot(&processes, &mut snapshotter);
+    let mut last_refresh = Instant::now();
     loop {
-        sys.refresh_processes_specifics(
-            ProcessesToUpdate::All,
-            true,
-            ProcessRefreshKind::nothing(),
-        );
-        let mut current_processes = HashSet::<String>::new();
-
-        for (_, process) in sys.processes() {
-            if let Some(name) = process.name().to_str() {
-                current_processes.insert(name.to_string());
-            }
+        if process_events.is_none() {
+            thread::sleep(pacer.scan_interval());
+            store_snapshot(&processes, &mut snapshotter);
+            continue;
         }
-        {
-            let processes = processes.lock();
-            if processes.is_err() {
-                continue;
+        match process_events.as_ref().unwrap().wait() {
+            Ok(true) => {
+                // Bursts of events, e.g. from a shell script, cause one refresh at most every
+                // minimum interval.
+                let since = last_refresh.elapsed();
+                if since < sysinfo::MINIMUM_CPU_UPDATE_INTERVAL {
+                    thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL - since);
+                }
+                // The monitor loop has to see the new snapshot when it wakes up.
+                store_snapshot(&processes, &mut snapshotter);
+                last_refresh = Instant::now();
+                processes_requested.store(false, Ordering::SeqCst);
+                processes_changed.notify();
+            }
+            Ok(false) => {
+                if processes_requested.swap(false, Ordering::SeqCst) {
+                    store_snapshot(&processes, &mut snapshotter);
+                    last_refresh = Instant::now();
+                }
             }
-            let mut processes = processes.unwrap();
-            *processes = current_processes;
+            Err(error) => {
+                warn!("{}, polling processes instead", error);
+                process_events = None;
+            }
+        }
+    }
+}
+
+fn store_snapshot(processes: &Mutex<Vec<ProcessInfo>>, snapshotter: &mut ProcessSnapshotter) {
+    let current_processes = snapshotter.refresh();
+    let processes = processes.lock();
+    if processes.is_err() {
+        error!("failed to lock processes");
+        return;
+    }
+    trace!("snapshot of {} processes", current_processes.len());
+    *processes.unwrap() = current_processes;
+}
+
+#[cfg(target_os = "linux")]
+struct ProcessEvents(crate::proc_connector::ProcConnector);
+
+#[cfg(target_os = "linux")]
+impl ProcessEvents {
+    fn open() -> Option<Self> {
+        let connector = crate::proc_connector::ProcConnector::open();
+        if let Err(error) = connector {
+            info!("polling processes, no process events: {}", error);
+            return None;
         }
-        std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+        info!("listening for process events");
+        Some(Self(connector.unwrap()))
+    }
+
+    // Returns true as soon as a process started, exited or was renamed, false when a second
+    // passed without one.
+    fn wait(&self) -> Result<bool, String> {
+        self.0.wait()
+    }
+}
+
+// Other platforms have no process events, the snapshot is always polled.
+#[cfg(not(target_os = "linux"))]
+struct ProcessEvents;
+
+#[cfg(not(target_os = "linux"))]
+impl ProcessEvents {
+    fn open() -> Option<Self> {
+        info!("polling processes");
+        None
+    }
+
+    fn wait(&self) -> Result<bool, String> {
+        Ok(false)
     }
 }
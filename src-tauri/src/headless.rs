@@ -0,0 +1,48 @@
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+
+use log::error;
+
+#[cfg(unix)]
+use crate::control_socket::ControlSocket;
+use crate::{
+    engine::{default_config_dir, Engine, EngineObserver},
+    logger::set_log_to_stderr,
+};
+
+// Runs the engine without a window or tray, e.g. as a systemd user service.
+// Uses the same config directory as the GUI and stops on SIGINT/SIGTERM.
+pub fn run_headless() {
+    let path = default_config_dir().and_then(|path| path.into_os_string().into_string().ok());
+    if path.is_none() {
+        eprintln!("failed to determine config directory");
+        return;
+    }
+    let path = path.unwrap();
+    set_log_to_stderr(true);
+    let engine = Engine::new();
+    engine.load(path.clone());
+
+    let (sender, receiver) = mpsc::channel();
+    let result = ctrlc::set_handler(move || {
+        let _ = sender.send(());
+    });
+    if result.is_err() {
+        error!("failed to register signal handler");
+        return;
+    }
+
+    let observers: Vec<Arc<dyn EngineObserver>> = vec![];
+    #[cfg(unix)]
+    let observers = {
+        let mut observers = observers;
+        if let Some(control_socket) = ControlSocket::start(&engine, Path::new(&path)) {
+            observers.push(control_socket);
+        }
+        observers
+    };
+    engine.start(Arc::new(observers));
+    let _ = receiver.recv();
+    engine.shutdown();
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    matcher::{MatchMode, MatchTarget},
+    mqtt_settings::MqttSettings,
+    process_entry::{new_entry_id, ProcessAction, ProcessEntry, DEFAULT_QOS, DEFAULT_RETAIN},
+};
+
+// Layouts of the bincode files stored before the config moved to JSON, kept so they can
+// still be imported after an upgrade.
+
+// Every start also set this brightness, the import turns it into an explicit action.
+// Stopping never changed the brightness, so its off value keeps it as it is.
+const LEGACY_BRIGHTNESS_TOPIC: &str = "tgn/esp_3/neopixel/brightness";
+const LEGACY_BRIGHTNESS_VALUE: &str = "150";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MqttSettingsV0 {
+    pub ip: String,
+    pub port: u16,
+}
+
+impl From<MqttSettingsV0> for MqttSettings {
+    fn from(settings: MqttSettingsV0) -> Self {
+        Self {
+            ip: settings.ip,
+            port: settings.port,
+            username: None,
+            password: None,
+            tls: None,
+            availability: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessEntryV0 {
+    pub is_running: bool,
+    pub name: String,
+    pub topic: String,
+    pub value: String,
+    pub off_value: String,
+}
+
+// The running state is not imported, the first scan decides it.
+impl From<ProcessEntryV0> for ProcessEntry {
+    fn from(entry: ProcessEntryV0) -> Self {
+        Self {
+            id: new_entry_id(),
+            is_running: false,
+            name: entry.name,
+            match_mode: MatchMode::Exact,
+            match_target: MatchTarget::Name,
+            priority: 0,
+            on_delay: 0,
+            off_delay: 0,
+            actions: vec![
+                ProcessAction {
+                    topic: entry.topic,
+                    value: entry.value,
+                    off_value: entry.off_value,
+                    qos: DEFAULT_QOS,
+                    retain: DEFAULT_RETAIN,
+                },
+                ProcessAction {
+                    topic: LEGACY_BRIGHTNESS_TOPIC.to_string(),
+                    value: LEGACY_BRIGHTNESS_VALUE.to_string(),
+                    off_value: LEGACY_BRIGHTNESS_VALUE.to_string(),
+                    qos: DEFAULT_QOS,
+                    retain: DEFAULT_RETAIN,
+                },
+            ],
+            pending_since: None,
+        }
+    }
+}
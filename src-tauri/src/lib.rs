@@ -1,56 +1,86 @@
-use std::{
-    collections::HashSet,
-    env,
-    sync::{Arc, Mutex},
-};
+use std::env;
 
 use commands::{
-    add_process_entry, delete_process_entry, get_autostart, get_mqtt_connection,
-    get_process_entrys, get_processes, mqtt_connect, open_config, save_mqtt_connection,
-    set_autostart, update_process_entry,
+    activate_profile, add_process_entry, create_profile, delete_process_entry, delete_profile,
+    export_entries, get_autostart, get_log_settings, get_mqtt_availability, get_mqtt_connection,
+    get_mqtt_tls, get_process_entrys, get_process_list, get_processes, get_profiles,
+    get_recent_logs, get_scan_settings, import_entries, mqtt_connect, open_config, rename_profile,
+    save_log_settings, save_mqtt_availability, save_mqtt_connection, save_mqtt_tls,
+    save_scan_settings, set_autostart, set_profile_broker, update_process_entry,
 };
 
-use logger::log;
-use mqtt::MqttClient;
-
 use crate::setup::setup;
-use fetch_processes::fetch_processes;
-use process_entry::ProcessEntry;
-use storage::Storage;
-use tauri::WindowEvent;
+use cli::{is_cli_command, run_cli};
+use engine::Engine;
+use headless::run_headless;
+use log::error;
+use tauri::{RunEvent, WindowEvent};
 use tauri_plugin_autostart::MacosLauncher;
 
+mod arbiter;
+mod cli;
 mod commands;
+mod config_watcher;
+#[cfg(windows)]
+mod console;
+#[cfg(unix)]
+mod control_socket;
+mod credentials;
+mod engine;
+mod error;
 mod fetch_processes;
+mod headless;
+mod legacy;
 mod logger;
+mod matcher;
 mod mqtt;
 mod mqtt_settings;
+mod operations;
+mod pacing;
+#[cfg(target_os = "linux")]
+mod proc_connector;
 mod process_entry;
+mod process_info;
+mod profiles;
 mod setup;
+mod shutdown;
 mod storage;
+mod transfer;
 
 const AUTO_START: &str = "--autostart";
+const HEADLESS: &str = "--headless";
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let storage = Arc::new(Mutex::new(Storage::new()));
-    let processes = Arc::new(Mutex::new(HashSet::<String>::new()));
-    let process_entrys = Arc::new(Mutex::new(Vec::<ProcessEntry>::new()));
-    let mqtt_client = Arc::new(Mutex::new(MqttClient::new()));
+    logger::init();
+    let args: Vec<String> = env::args().collect();
+    if is_cli_command(&args) {
+        #[cfg(windows)]
+        console::attach_console(false);
+        std::process::exit(run_cli(&args));
+    }
+    if args.contains(&HEADLESS.to_owned()) {
+        // Started from a shell the log and Ctrl+C go through its console, otherwise e.g. from
+        // the task scheduler a console of its own is opened.
+        #[cfg(windows)]
+        console::attach_console(true);
+        run_headless();
+        return;
+    }
+    let engine = Engine::new();
+    let engine_setup_clone = engine.clone();
 
-    let error = tauri::Builder::default()
+    let app = tauri::Builder::default()
         .plugin(tauri_plugin_autostart::init(
             MacosLauncher::LaunchAgent,
             Some(vec![AUTO_START]),
         ))
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
-        .manage(processes.clone())
-        .manage(process_entrys.clone())
-        .manage(mqtt_client.clone())
-        .manage(storage.clone())
+        .manage(engine.clone())
         .invoke_handler(tauri::generate_handler![
             get_processes,
+            get_process_list,
             update_process_entry,
             get_process_entrys,
             mqtt_connect,
@@ -59,11 +89,28 @@ pub fn run() {
             open_config,
             get_mqtt_connection,
             save_mqtt_connection,
+            get_mqtt_tls,
+            save_mqtt_tls,
+            get_mqtt_availability,
+            save_mqtt_availability,
+            get_profiles,
+            create_profile,
+            rename_profile,
+            delete_profile,
+            activate_profile,
+            set_profile_broker,
+            export_entries,
+            import_entries,
+            get_log_settings,
+            save_log_settings,
+            get_recent_logs,
+            get_scan_settings,
+            save_scan_settings,
             set_autostart,
             get_autostart
         ])
         .setup(move |app| {
-            setup(app, storage, process_entrys, mqtt_client, processes);
+            setup(app, engine_setup_clone);
             Ok(())
         })
         .on_window_event(|window, event| match event {
@@ -71,13 +118,20 @@ pub fn run() {
                 api.prevent_close();
                 let result = window.hide();
                 if result.is_err() {
-                    log("failed to hide window");
+                    error!("failed to hide window");
                 }
             }
             _ => (),
         })
-        .run(tauri::generate_context!());
-    if error.is_err() {
+        .build(tauri::generate_context!());
+    if app.is_err() {
         eprintln!("error starting app");
+        return;
     }
+    // Covers the tray quit, signals and the OS ending the session.
+    app.unwrap().run(move |_, event| {
+        if let RunEvent::Exit = event {
+            engine.shutdown();
+        }
+    });
 }
@@ -1,58 +1,381 @@
-use std::io::Write;
-use std::path::PathBuf;
 use std::{
-    fs::OpenOptions,
-    sync::{LazyLock, Mutex},
+    env,
+    fs::{remove_file, rename, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
 };
 
 use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
 
-pub static LOG_FILE_PATH: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+// The GUI or headless engine writes `log.txt`, the CLI `cli.txt`. Each process rotates only
+// its own files, so neither renames a file the other is writing to.
+pub const LOG_FILE_NAME: &str = "log";
+pub const CLI_LOG_FILE_NAME: &str = "cli";
+const LOG_FILE_EXTENSION: &str = "txt";
+// Overrides the configured level, e.g. PROCESS_COLOR_LOG=debug.
+const LEVEL_ENV: &str = "PROCESS_COLOR_LOG";
+// Dependencies like Tauri log through the same facade, only their warnings are kept.
+const OWN_TARGET: &str = "process_color_lib";
+const MIN_FILE_SIZE: u64 = 16 * 1024;
+const MAX_FILES: u32 = 100;
 
-pub fn log(message: &str) {
-    let log_file_path = LOG_FILE_PATH.lock();
-    if log_file_path.is_err() {
-        return;
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
     }
-    let log_file_path = log_file_path.unwrap();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LogSettings {
+    pub level: LogLevel,
+    // Bytes after which `log.txt` is rotated to `log.1.txt`, likewise for `cli.txt`.
+    pub max_file_size: u64,
+    // Rotated files that are kept besides `log.txt`.
+    pub max_files: u32,
+}
+
+impl LogSettings {
+    pub const fn new() -> Self {
+        Self {
+            level: LogLevel::Info,
+            max_file_size: 1024 * 1024,
+            max_files: 5,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_file_size < MIN_FILE_SIZE {
+            return Err(format!(
+                "log files must be allowed at least {} bytes",
+                MIN_FILE_SIZE
+            ));
+        }
+        if self.max_files > MAX_FILES {
+            return Err(format!(
+                "at most {} rotated log files can be kept",
+                MAX_FILES
+            ));
+        }
+        Ok(())
+    }
+}
+
+struct LogFile {
+    directory: PathBuf,
+    name: &'static str,
+    file: Option<File>,
+    size: u64,
+}
+
+struct FileLogger {
+    settings: Mutex<LogSettings>,
+    // Nothing is written until the config directory is known, see `set_log_path`.
+    file: Mutex<Option<LogFile>>,
+    // Headless mode also logs to stderr, so a service manager picks the lines up.
+    stderr: Mutex<bool>,
+}
+
+static LOGGER: FileLogger = FileLogger {
+    settings: Mutex::new(LogSettings::new()),
+    file: Mutex::new(None),
+    stderr: Mutex::new(false),
+};
 
-    if log_file_path.is_none() {
+// Installs the logger behind the `log` macros, call once before anything is logged.
+pub fn init() {
+    if log::set_logger(&LOGGER).is_err() {
+        eprintln!("failed to install logger");
         return;
     }
-    let log_file_path = log_file_path.as_ref().unwrap();
+    log::set_max_level(max_level(LogLevel::Info));
+}
 
-    let log_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(log_file_path);
-    if log_file.is_err() {
+pub fn set_log_path(path: String, name: &'static str) {
+    let file = LOGGER.file.lock();
+    if file.is_err() {
         return;
     }
-    let mut log_file = log_file.unwrap();
+    *file.unwrap() = Some(LogFile {
+        directory: PathBuf::from(path),
+        name,
+        file: None,
+        size: 0,
+    });
+}
 
-    let _ = writeln!(
-        log_file,
-        "{}",
-        format!(
-            "[{}] - {}",
-            Local::now().format("%Y-%m-%dT%H:%M:%S"),
-            message
-        )
-    );
+pub fn set_log_to_stderr(enabled: bool) {
+    if let Ok(mut stderr) = LOGGER.stderr.lock() {
+        *stderr = enabled;
+    }
 }
 
-pub fn set_log_path(path: String) {
-    let log_file_path = LOG_FILE_PATH.lock();
-    if log_file_path.is_err() {
+pub fn apply_log_settings(settings: &LogSettings) {
+    let current = LOGGER.settings.lock();
+    if current.is_err() {
         return;
     }
-    let mut log_file_path = log_file_path.unwrap();
+    *current.unwrap() = settings.clone();
+    log::set_max_level(max_level(settings.level));
+}
+
+pub fn get_log_settings() -> LogSettings {
+    LOGGER
+        .settings
+        .lock()
+        .map(|settings| settings.clone())
+        .unwrap_or_else(|_| LogSettings::new())
+}
+
+// The last lines of the log, oldest first, reaching into the rotated file if needed.
+pub fn recent_log_lines(count: usize) -> Vec<String> {
+    let location = LOGGER.file.lock().ok().and_then(|file| {
+        file.as_ref()
+            .map(|file| (file.directory.clone(), file.name))
+    });
+    if location.is_none() {
+        return vec![];
+    }
+    let (directory, name) = location.unwrap();
+    let mut lines = read_lines(&log_path(&directory, name, 0));
+    if lines.len() < count {
+        let mut older = read_lines(&log_path(&directory, name, 1));
+        older.append(&mut lines);
+        lines = older;
+    }
+    let skip = lines.len().saturating_sub(count);
+    lines.split_off(skip)
+}
+
+fn max_level(configured: LogLevel) -> LevelFilter {
+    env::var(LEVEL_ENV)
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(configured.into())
+}
 
-    *log_file_path = Some(
-        PathBuf::from(path)
-            .join("log.txt")
-            .to_string_lossy()
-            .to_string(),
-    );
+// `log.txt` for the current file, `log.<n>.txt` for rotated ones.
+fn log_path(directory: &Path, name: &str, index: u32) -> PathBuf {
+    if index == 0 {
+        return directory.join(format!("{}.{}", name, LOG_FILE_EXTENSION));
+    }
+    directory.join(format!("{}.{}.{}", name, index, LOG_FILE_EXTENSION))
+}
+
+fn read_lines(path: &Path) -> Vec<String> {
+    let file = File::open(path);
+    if file.is_err() {
+        return vec![];
+    }
+    BufReader::new(file.unwrap())
+        .lines()
+        .map_while(Result::ok)
+        .collect()
+}
+
+impl LogFile {
+    fn write(&mut self, line: &str, settings: &LogSettings) {
+        if self.file.is_none() && !self.open() {
+            return;
+        }
+        if self.size > 0 && self.size + line.len() as u64 > settings.max_file_size {
+            self.rotate(settings.max_files);
+            // When the file could not be moved, e.g. because another process holds it open
+            // on Windows, the line is appended anyway and rotation is tried on the next one.
+            if !self.open() {
+                return;
+            }
+        }
+        let file = self.file.as_mut().unwrap();
+        if file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+
+    // Opens `log.txt` for appending, returns false when that failed.
+    fn open(&mut self) -> bool {
+        let path = log_path(&self.directory, self.name, 0);
+        let file = OpenOptions::new().create(true).append(true).open(&path);
+        if file.is_err() {
+            return false;
+        }
+        let file = file.unwrap();
+        self.size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        self.file = Some(file);
+        true
+    }
+
+    // Shifts every file up by one, the oldest one beyond `max_files` is dropped. Without
+    // rotated files the log simply starts over.
+    fn rotate(&mut self, max_files: u32) {
+        self.file = None;
+        let _ = remove_file(log_path(&self.directory, self.name, max_files));
+        for index in (0..max_files).rev() {
+            let _ = rename(
+                log_path(&self.directory, self.name, index),
+                log_path(&self.directory, self.name, index + 1),
+            );
+        }
+        self.size = 0;
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if !metadata.target().starts_with(OWN_TARGET) {
+            return metadata.level() <= Level::Warn;
+        }
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "[{}] {:<5} {} - {}\n",
+            Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if self.stderr.lock().is_ok_and(|stderr| *stderr) {
+            eprint!("{}", line);
+        }
+        let settings = self.settings.lock().map(|settings| settings.clone());
+        if settings.is_err() {
+            return;
+        }
+        let file = self.file.lock();
+        if file.is_err() {
+            return;
+        }
+        if let Some(file) = file.unwrap().as_mut() {
+            file.write(&line, &settings.unwrap());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all};
+
+    use super::*;
+    use crate::process_entry::new_entry_id;
+
+    fn temp_log_file(name: &'static str) -> LogFile {
+        let directory = env::temp_dir().join(format!("process-color-{}", new_entry_id()));
+        create_dir_all(&directory).unwrap();
+        LogFile {
+            directory,
+            name,
+            file: None,
+            size: 0,
+        }
+    }
+
+    fn settings(max_file_size: u64, max_files: u32) -> LogSettings {
+        LogSettings {
+            level: LogLevel::Info,
+            max_file_size,
+            max_files,
+        }
+    }
+
+    fn contents(file: &LogFile, index: u32) -> Option<String> {
+        read_to_string(log_path(&file.directory, file.name, index)).ok()
+    }
+
+    #[test]
+    fn rotates_when_the_file_is_full() {
+        let mut file = temp_log_file(LOG_FILE_NAME);
+        let settings = settings(10, 2);
+        file.write("first\n", &settings);
+        file.write("second\n", &settings);
+        file.write("third\n", &settings);
+        file.write("fourth\n", &settings);
+        assert_eq!(contents(&file, 0).as_deref(), Some("fourth\n"));
+        assert_eq!(contents(&file, 1).as_deref(), Some("third\n"));
+        assert_eq!(contents(&file, 2).as_deref(), Some("second\n"));
+        assert_eq!(contents(&file, 3), None);
+        remove_dir_all(&file.directory).unwrap();
+    }
+
+    #[test]
+    fn starts_over_without_rotated_files() {
+        let mut file = temp_log_file(LOG_FILE_NAME);
+        let settings = settings(10, 0);
+        file.write("first\n", &settings);
+        file.write("second\n", &settings);
+        assert_eq!(contents(&file, 0).as_deref(), Some("second\n"));
+        assert_eq!(contents(&file, 1), None);
+        remove_dir_all(&file.directory).unwrap();
+    }
+
+    #[test]
+    fn continues_an_existing_file() {
+        let mut file = temp_log_file(LOG_FILE_NAME);
+        let settings = settings(16, 1);
+        file.write("first\n", &settings);
+        let mut reopened = LogFile {
+            directory: file.directory.clone(),
+            name: file.name,
+            file: None,
+            size: 0,
+        };
+        reopened.write("second\n", &settings);
+        reopened.write("third\n", &settings);
+        assert_eq!(contents(&file, 0).as_deref(), Some("third\n"));
+        assert_eq!(contents(&file, 1).as_deref(), Some("first\nsecond\n"));
+        remove_dir_all(&file.directory).unwrap();
+    }
+
+    #[test]
+    fn cli_rotates_only_its_own_files() {
+        let mut engine = temp_log_file(LOG_FILE_NAME);
+        let mut cli = LogFile {
+            directory: engine.directory.clone(),
+            name: CLI_LOG_FILE_NAME,
+            file: None,
+            size: 0,
+        };
+        let settings = settings(10, 1);
+        engine.write("engine\n", &settings);
+        cli.write("first\n", &settings);
+        cli.write("second\n", &settings);
+        assert_eq!(contents(&engine, 0).as_deref(), Some("engine\n"));
+        assert_eq!(contents(&engine, 1), None);
+        assert_eq!(contents(&cli, 0).as_deref(), Some("second\n"));
+        assert_eq!(contents(&cli, 1).as_deref(), Some("first\n"));
+        remove_dir_all(&engine.directory).unwrap();
+    }
+
+    #[test]
+    fn validates_settings() {
+        assert!(LogSettings::new().validate().is_ok());
+        assert!(settings(MIN_FILE_SIZE, 0).validate().is_ok());
+        assert!(settings(MIN_FILE_SIZE - 1, 0).validate().is_err());
+        assert!(settings(MIN_FILE_SIZE, MAX_FILES).validate().is_ok());
+        assert!(settings(MIN_FILE_SIZE, MAX_FILES + 1).validate().is_err());
+    }
 }
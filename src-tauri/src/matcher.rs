@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    Exact,
+    CaseInsensitive,
+    Glob,
+    Regex,
+    Substring,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchTarget {
+    Name,
+    ExePath,
+    CommandLine,
+}
+
+pub enum Matcher {
+    Exact(String),
+    CaseInsensitive(String),
+    Substring(String),
+    Pattern(Regex),
+}
+
+impl Matcher {
+    pub fn new(mode: MatchMode, pattern: &str) -> Result<Self, String> {
+        match mode {
+            MatchMode::Exact => Ok(Self::Exact(pattern.to_string())),
+            MatchMode::CaseInsensitive => Ok(Self::CaseInsensitive(pattern.to_lowercase())),
+            MatchMode::Substring if pattern.is_empty() => {
+                Err("empty substring would match every process".to_string())
+            }
+            MatchMode::Substring => Ok(Self::Substring(pattern.to_string())),
+            MatchMode::Glob => Self::regex(&glob_to_regex(pattern))
+                .map_err(|error| format!("invalid glob pattern \"{}\": {}", pattern, error)),
+            MatchMode::Regex if pattern.is_empty() => {
+                Err("empty regex would match every process".to_string())
+            }
+            MatchMode::Regex => Self::regex(pattern)
+                .map_err(|error| format!("invalid regex \"{}\": {}", pattern, error)),
+        }
+    }
+
+    fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self::Pattern(Regex::new(pattern)?))
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            Self::Exact(pattern) => name == pattern,
+            Self::CaseInsensitive(pattern) => name.to_lowercase() == *pattern,
+            Self::Substring(pattern) => name.contains(pattern.as_str()),
+            Self::Pattern(regex) => regex.is_match(name),
+        }
+    }
+}
+
+// Anchored translation of `*`, `?` and `[...]`, everything else matches literally.
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::from("^");
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                if let Some((class, consumed)) = glob_class(&chars[index + 1..]) {
+                    regex.push_str(&class);
+                    index += consumed + 1;
+                    continue;
+                }
+                // Without a closing `]` the bracket matches itself.
+                regex.push_str("\\[");
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        index += 1;
+    }
+    regex.push('$');
+    regex
+}
+
+// Translates the class following a `[`, with the number of chars it took including the
+// closing `]`. A leading `!` negates it, a `]` right at the start is a member and `-` between
+// two members is a range, every other char is escaped so regex class operators like `&&`
+// stay literal.
+fn glob_class(chars: &[char]) -> Option<(String, usize)> {
+    let negated = chars.first() == Some(&'!');
+    let start = usize::from(negated);
+    let end = chars
+        .iter()
+        .skip(start + 1)
+        .position(|&c| c == ']')
+        .map(|position| position + start + 1)?;
+    let members = &chars[start..end];
+    let mut class = String::from(if negated { "[^" } else { "[" });
+    for (index, &c) in members.iter().enumerate() {
+        if c == '-' && index > 0 && index < members.len() - 1 {
+            class.push('-');
+        } else {
+            class.push_str(&regex::escape(&c.to_string()));
+        }
+    }
+    class.push(']');
+    Some((class, end + 1))
+}
+
+// Compiled matchers by mode and pattern, so patterns are only compiled when they change.
+pub struct MatcherCache {
+    matchers: HashMap<(MatchMode, String), Option<Matcher>>,
+}
+
+impl MatcherCache {
+    pub fn new() -> Self {
+        Self {
+            matchers: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, mode: MatchMode, pattern: &str) -> Option<&Matcher> {
+        self.matchers
+            .entry((mode, pattern.to_string()))
+            .or_insert_with(|| {
+                let matcher = Matcher::new(mode, pattern);
+                if let Err(error) = &matcher {
+                    warn!("{}", error);
+                }
+                matcher.ok()
+            })
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Matcher {
+        Matcher::new(MatchMode::Glob, pattern).unwrap()
+    }
+
+    #[test]
+    fn translates_wildcards() {
+        assert_eq!(glob_to_regex("obs*.exe"), "^obs.*\\.exe$");
+        assert!(glob("obs*.exe").is_match("obs64.exe"));
+        assert!(glob("obs?.exe").is_match("obs6.exe"));
+        assert!(!glob("obs?.exe").is_match("obs64.exe"));
+    }
+
+    #[test]
+    fn matches_other_chars_literally() {
+        assert!(glob("a.b+c").is_match("a.b+c"));
+        assert!(!glob("a.b+c").is_match("axbbc"));
+        assert!(glob("(x){2}").is_match("(x){2}"));
+    }
+
+    #[test]
+    fn translates_classes() {
+        assert!(glob("[abc]").is_match("b"));
+        assert!(!glob("[abc]").is_match("d"));
+        assert!(glob("[a-c]").is_match("b"));
+        assert!(glob("[!a-c]").is_match("d"));
+        assert!(!glob("[!a-c]").is_match("b"));
+    }
+
+    #[test]
+    fn keeps_regex_class_operators_literal() {
+        assert_eq!(glob_to_regex("[a&&b]"), "^[a\\&\\&b]$");
+        assert!(glob("[a&&b]").is_match("&"));
+        assert!(glob("[a~~b]").is_match("~"));
+        assert!(glob("[a\\b]").is_match("\\"));
+        assert!(glob("[a[b]").is_match("["));
+        assert!(glob("[^a]").is_match("^"));
+    }
+
+    #[test]
+    fn treats_dash_at_the_edges_as_member() {
+        assert!(glob("[-a]").is_match("-"));
+        assert!(glob("[a-]").is_match("-"));
+        assert!(!glob("[a-]").is_match("b"));
+    }
+
+    #[test]
+    fn treats_leading_bracket_as_member() {
+        assert!(glob("[]]").is_match("]"));
+        assert!(glob("[!]]").is_match("a"));
+        assert!(!glob("[!]]").is_match("]"));
+    }
+
+    #[test]
+    fn treats_unterminated_bracket_as_literal() {
+        assert!(glob("[abc").is_match("[abc"));
+        assert!(glob("[]").is_match("[]"));
+        assert!(glob("a[!").is_match("a[!"));
+    }
+}
@@ -1,11 +1,40 @@
-use std::time::Duration;
+use std::{
+    net::ToSocketAddrs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
 
-use paho_mqtt::{Client, ConnectOptionsBuilder, MessageBuilder};
+use log::{error, info, warn};
+use paho_mqtt::{
+    AsyncClient, ConnectOptions, ConnectOptionsBuilder, CreateOptionsBuilder, DeliveryToken,
+    Message, MessageBuilder, SslOptions, SslOptionsBuilder,
+};
 
-use crate::{log, mqtt_settings::MqttSettings};
+use crate::{
+    error::Error,
+    mqtt_settings::{AvailabilitySettings, MqttSettings, TlsSettings},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const AVAILABILITY_QOS: i32 = 1;
+// Messages published while the connection is down are buffered and sent after reconnecting.
+const MAX_BUFFERED_MESSAGES: i32 = 100;
+// paho only reconnects by itself after a successful first connect, until then we retry.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
 
 pub struct MqttClient {
-    client: Option<Client>,
+    client: Option<AsyncClient>,
+    // Availability of the current connection, settings may already have changed on reconnect.
+    availability: Option<AvailabilitySettings>,
+    resync_requested: Arc<AtomicBool>,
+    // Ends the connect retries of the current client once it is replaced.
+    stop_retrying: Arc<AtomicBool>,
+    // Why the last connect failed, see `take_connect_error`.
+    connect_error: Arc<Mutex<Option<String>>>,
     pub settings: Option<MqttSettings>,
 }
 
@@ -13,56 +42,184 @@ impl MqttClient {
     pub fn new() -> Self {
         Self {
             client: None,
+            availability: None,
+            resync_requested: Arc::new(AtomicBool::new(false)),
+            stop_retrying: Arc::new(AtomicBool::new(false)),
+            connect_error: Arc::new(Mutex::new(None)),
             settings: None,
         }
     }
-    pub fn connect(&mut self) {
-        if let Some(client) = &self.client {
-            let result = client.disconnect(None);
-            if result.is_err() {
-                log("error disconnecting from MQTT broker");
-            }
-        }
-        self.client = None;
+    // Connects in the background, for callers that only log a failure. The reason is reported
+    // through `take_connect_error`.
+    pub fn connect(&mut self) -> Result<(), Error> {
+        self.start_connect().map(ConnectAttempt::in_background)
+    }
+
+    // Replaces the client, the returned attempt connects it once the caller released its
+    // locks, so nobody waits for DNS or the broker meanwhile. Messages published until then
+    // are buffered.
+    pub fn start_connect(&mut self) -> Result<ConnectAttempt, Error> {
+        let (client, options, address) = self.create_client()?;
+        self.stop_retrying.store(true, Ordering::SeqCst);
+        let previous = self
+            .client
+            .take()
+            .map(|previous| (previous, self.availability.take()));
+        self.availability = self.settings.as_ref().unwrap().availability.clone();
+        self.client = Some(client.clone());
+        self.stop_retrying = Arc::new(AtomicBool::new(false));
+        self.connect_error = Arc::new(Mutex::new(None));
+        Ok(ConnectAttempt {
+            previous,
+            client,
+            options,
+            address,
+            stop_retrying: self.stop_retrying.clone(),
+            connect_error: self.connect_error.clone(),
+        })
+    }
+
+    // Waits for the first connect and does not retry, for one-off connections like the CLI test.
+    pub fn connect_blocking(&mut self) -> Result<(), Error> {
+        self.disconnect();
+        let (client, options, address) = self.create_client()?;
+        try_connect(&client, &options, &address)?;
+        self.availability = self.settings.as_ref().unwrap().availability.clone();
+        self.client = Some(client);
+        Ok(())
+    }
+
+    fn create_client(&self) -> Result<(AsyncClient, ConnectOptions, (String, u16)), Error> {
         if self.settings.is_none() {
-            return;
+            return Err(Error::NotConfigured("no MQTT settings"));
         }
         let settings = self.settings.as_ref().unwrap();
-        let client = Client::new(format!("tcp://{}:{}", settings.ip, settings.port));
-        if client.is_err() {
-            self.client = None;
-            log("error creating MQTT client");
-            return;
-        }
-        let mut client = client.unwrap();
-        client.set_timeout(Duration::from_secs(5));
+        let scheme = if settings.tls.is_some() { "ssl" } else { "tcp" };
+        let client = CreateOptionsBuilder::new()
+            .server_uri(format!("{}://{}:{}", scheme, settings.ip, settings.port))
+            .send_while_disconnected(true)
+            .max_buffered_messages(MAX_BUFFERED_MESSAGES)
+            .allow_disconnected_send_at_anytime(true)
+            .delete_oldest_messages(true)
+            .create_client();
+        let client = client?;
         let interval = Duration::new(1, 0);
-        let response = client.connect(
-            ConnectOptionsBuilder::new()
-                .automatic_reconnect(interval, interval)
-                .finalize(),
-        );
-        if response.is_err() {
-            self.client = None;
-            log("error connecting to MQTT broker");
-            return;
+        let mut options = ConnectOptionsBuilder::new();
+        options.automatic_reconnect(interval, interval);
+        if let Some(username) = &settings.username {
+            options.user_name(username);
         }
-        self.client = Some(client);
+        if let Some(password) = &settings.password {
+            options.password(password);
+        }
+        if let Some(tls) = &settings.tls {
+            let ssl_options =
+                Self::ssl_options(tls).map_err(|error| Error::MqttTls(error.to_string()))?;
+            options.ssl_options(ssl_options);
+        }
+        let mut online = None;
+        if let Some(availability) = &settings.availability {
+            options.will_message(Self::availability_message(
+                availability,
+                &availability.offline_payload,
+            ));
+            online = Some(Self::availability_message(
+                availability,
+                &availability.online_payload,
+            ));
+        }
+        // Runs after the initial connect and after every automatic reconnect.
+        let resync_requested = self.resync_requested.clone();
+        client.set_connected_callback(move |client| {
+            if let Some(online) = &online {
+                if client.try_publish(online.clone()).is_err() {
+                    warn!("error publishing online message");
+                }
+            }
+            resync_requested.store(true, Ordering::SeqCst);
+        });
+        Ok((
+            client,
+            options.finalize(),
+            (settings.ip.clone(), settings.port),
+        ))
+    }
+
+    // The reason the first connect failed, reported once.
+    pub fn take_connect_error(&self) -> Option<String> {
+        self.connect_error
+            .lock()
+            .ok()
+            .and_then(|mut connect_error| connect_error.take())
+    }
+
+    // Blocks until the offline message is sent, use it when the app exits.
+    pub fn disconnect(&mut self) {
+        self.stop_retrying.store(true, Ordering::SeqCst);
+        if let Some(client) = self.client.take() {
+            disconnect(&client, self.availability.as_ref());
+        }
+        self.availability = None;
+    }
+
+    fn availability_message(availability: &AvailabilitySettings, payload: &str) -> Message {
+        MessageBuilder::new()
+            .topic(&availability.topic)
+            .payload(payload)
+            .qos(AVAILABILITY_QOS)
+            .retained(true)
+            .finalize()
     }
 
-    pub fn publish(&self, topic: &String, value: &String) {
+    fn ssl_options(tls: &TlsSettings) -> paho_mqtt::Result<SslOptions> {
+        let mut builder = SslOptionsBuilder::new();
+        builder.enable_server_cert_auth(true);
+        builder.verify(tls.verify_hostname);
+        if let Some(ca_file) = &tls.ca_file {
+            builder.trust_store(ca_file)?;
+        }
+        if let Some(client_cert_file) = &tls.client_cert_file {
+            builder.key_store(client_cert_file)?;
+        }
+        if let Some(client_key_file) = &tls.client_key_file {
+            builder.private_key(client_key_file)?;
+        }
+        Ok(builder.finalize())
+    }
+
+    pub fn publish(
+        &self,
+        topic: &str,
+        value: &str,
+        qos: i32,
+        retain: bool,
+    ) -> Option<DeliveryToken> {
         if let Some(client) = &self.client {
             let msg = MessageBuilder::new()
                 .topic(topic)
-                .payload(value.clone())
-                .qos(3)
-                .retained(true)
+                .payload(value)
+                .qos(qos)
+                .retained(retain)
                 .finalize();
 
-            if client.publish(msg).is_err() {
-                log("error publishing message");
+            let token = client.try_publish(msg);
+            if token.is_err() {
+                error!("error publishing message");
+                return None;
             }
+            return Some(token.unwrap());
         }
+        None
+    }
+
+    // Makes the monitor loop publish the full state again, after the entries were swapped.
+    pub fn request_resync(&self) {
+        self.resync_requested.store(true, Ordering::SeqCst);
+    }
+
+    // Set after every (re)connect, the broker may have missed state changes while disconnected.
+    pub fn take_resync_request(&self) -> bool {
+        self.resync_requested.swap(false, Ordering::SeqCst)
     }
 
     pub fn is_connected(&self) -> bool {
@@ -72,3 +229,98 @@ impl MqttClient {
         false
     }
 }
+
+// A replaced client, the first connect of the new one and its retries. Run it without
+// holding the `MqttClient` lock.
+pub struct ConnectAttempt {
+    // Disconnected first, so its offline message can not arrive after the new online one.
+    previous: Option<(AsyncClient, Option<AvailabilitySettings>)>,
+    client: AsyncClient,
+    options: ConnectOptions,
+    address: (String, u16),
+    stop_retrying: Arc<AtomicBool>,
+    connect_error: Arc<Mutex<Option<String>>>,
+}
+
+impl ConnectAttempt {
+    // Returns the result of the first connect, a failed one is retried in the background.
+    pub fn wait(self) -> Result<(), Error> {
+        let result = self.first_connect();
+        if let Err(error) = &result {
+            warn!("{}, retrying in the background", error);
+            thread::spawn(move || self.retry());
+        }
+        result
+    }
+
+    // Connects and retries in the background, the first failure is kept for
+    // `take_connect_error`.
+    pub fn in_background(self) {
+        thread::spawn(move || {
+            let result = self.first_connect();
+            if let Err(error) = result {
+                warn!("{}, retrying in the background", error);
+                if let Ok(mut connect_error) = self.connect_error.lock() {
+                    *connect_error = Some(error.to_string());
+                }
+                self.retry();
+            }
+        });
+    }
+
+    fn first_connect(&self) -> Result<(), Error> {
+        if let Some((client, availability)) = &self.previous {
+            disconnect(client, availability.as_ref());
+        }
+        self.try_connect()
+    }
+
+    fn retry(self) {
+        loop {
+            thread::sleep(RETRY_INTERVAL);
+            if self.stop_retrying.load(Ordering::SeqCst) || self.try_connect().is_ok() {
+                break;
+            }
+        }
+    }
+
+    fn try_connect(&self) -> Result<(), Error> {
+        let result = try_connect(&self.client, &self.options, &self.address);
+        if result.is_ok() && self.stop_retrying.load(Ordering::SeqCst) {
+            // Replaced while connecting, the new client owns the connection now.
+            let _ = self.client.disconnect(None).wait_for(TIMEOUT);
+        }
+        result
+    }
+}
+
+fn try_connect(
+    client: &AsyncClient,
+    options: &ConnectOptions,
+    address: &(String, u16),
+) -> Result<(), Error> {
+    // paho only reports a generic connect failure for unknown hosts.
+    let addresses = (address.0.as_str(), address.1).to_socket_addrs();
+    if addresses.is_err() || addresses.unwrap().next().is_none() {
+        return Err(Error::MqttDns(address.0.clone()));
+    }
+    client.connect(options.clone()).wait_for(TIMEOUT)?;
+    info!("connected to MQTT broker {}:{}", address.0, address.1);
+    Ok(())
+}
+
+fn disconnect(client: &AsyncClient, availability: Option<&AvailabilitySettings>) {
+    if !client.is_connected() {
+        return;
+    }
+    // The will is only sent by the broker on an unexpected disconnect.
+    if let Some(availability) = availability {
+        let offline = MqttClient::availability_message(availability, &availability.offline_payload);
+        if client.publish(offline).wait_for(TIMEOUT).is_err() {
+            warn!("error publishing offline message");
+        }
+    }
+    if client.disconnect(None).wait_for(TIMEOUT).is_err() {
+        warn!("error disconnecting from MQTT broker");
+    }
+}
@@ -4,4 +4,23 @@ use serde::{Deserialize, Serialize};
 pub struct MqttSettings {
     pub ip: String,
     pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: Option<TlsSettings>,
+    pub availability: Option<AvailabilitySettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TlsSettings {
+    pub ca_file: Option<String>,
+    pub client_cert_file: Option<String>,
+    pub client_key_file: Option<String>,
+    pub verify_hostname: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AvailabilitySettings {
+    pub topic: String,
+    pub online_payload: String,
+    pub offline_payload: String,
 }
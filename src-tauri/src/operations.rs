@@ -0,0 +1,456 @@
+use std::{collections::HashSet, sync::atomic::Ordering};
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::{
+    engine::Engine,
+    error::Error,
+    logger::{apply_log_settings, get_log_settings, recent_log_lines, LogSettings},
+    matcher::{MatchMode, MatchTarget},
+    mqtt_settings::{AvailabilitySettings, MqttSettings, TlsSettings},
+    pacing::ScanSettings,
+    process_entry::{validate_entry, ProcessAction, ProcessEntry},
+    process_info::ProcessInfo,
+    profiles::{ProfileList, Profiles},
+    shutdown::turn_off,
+    transfer::{self, ImportMode, ImportSummary},
+};
+
+const MAX_LOG_LINES: usize = 10_000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProcessEntryUpdate {
+    pub id: String,
+    pub name: String,
+    pub match_mode: MatchMode,
+    pub match_target: MatchTarget,
+    pub priority: i32,
+    pub on_delay: u64,
+    pub off_delay: u64,
+    pub actions: Vec<ProcessAction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttConnectionUpdate {
+    pub ip: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+// The operations behind the Tauri commands and the control socket.
+impl Engine {
+    pub fn get_process_entrys(&self) -> Result<Vec<ProcessEntry>, Error> {
+        let entries = self
+            .process_entrys
+            .lock()
+            .map_err(|_| Error::Lock("process entries"))?;
+        Ok(entries.clone())
+    }
+
+    pub fn add_process_entry(&self) -> Result<String, Error> {
+        let mut process_entrys = self
+            .process_entrys
+            .lock()
+            .map_err(|_| Error::Lock("process entrys"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        let entry = ProcessEntry::new();
+        let id = entry.id.clone();
+        process_entrys.push(entry);
+        storage.save_process_entrys(&process_entrys)?;
+        Ok(id)
+    }
+
+    pub fn update_process_entry(&self, update: ProcessEntryUpdate) -> Result<(), Error> {
+        validate_entry(&update.name, update.match_mode, &update.actions)?;
+        let mut process_entrys = self
+            .process_entrys
+            .lock()
+            .map_err(|_| Error::Lock("process entrys"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        let entry = process_entrys
+            .iter_mut()
+            .find(|entry| entry.id == update.id);
+        if entry.is_none() {
+            return Err(Error::NotFound(format!(
+                "unknown process entry {}",
+                update.id
+            )));
+        }
+        let entry = entry.unwrap();
+        entry.name = update.name;
+        entry.match_mode = update.match_mode;
+        entry.match_target = update.match_target;
+        entry.priority = update.priority;
+        entry.on_delay = update.on_delay;
+        entry.off_delay = update.off_delay;
+        entry.actions = update.actions;
+        storage.save_process_entrys(&process_entrys)
+    }
+
+    pub fn delete_process_entry(&self, id: &str) -> Result<(), Error> {
+        let mut process_entrys = self
+            .process_entrys
+            .lock()
+            .map_err(|_| Error::Lock("process entrys"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        let index = process_entrys.iter().position(|entry| entry.id == id);
+        if index.is_none() {
+            return Err(Error::NotFound(format!("unknown process entry {}", id)));
+        }
+        process_entrys.remove(index.unwrap());
+        storage.save_process_entrys(&process_entrys)
+    }
+
+    pub fn get_processes(&self) -> Result<Vec<String>, Error> {
+        let processes = self
+            .processes
+            .lock()
+            .map_err(|_| Error::Lock("processes"))?;
+        let mut processes = processes
+            .iter()
+            .map(|process| process.name.clone())
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect::<Vec<String>>();
+        processes.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()));
+        Ok(processes)
+    }
+
+    pub fn get_process_list(&self) -> Result<Vec<ProcessInfo>, Error> {
+        // The next read gets fresh CPU usage and memory.
+        self.processes_requested.store(true, Ordering::SeqCst);
+        let mut processes = self
+            .processes
+            .lock()
+            .map_err(|_| Error::Lock("processes"))?
+            .clone();
+        processes.sort_by_key(|process| (process.name.to_lowercase(), process.pid));
+        Ok(processes)
+    }
+
+    pub fn mqtt_connect(&self) -> Result<(), Error> {
+        let mut mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        let attempt = mqtt_client.start_connect()?;
+        drop(mqtt_client);
+        attempt.wait()
+    }
+
+    pub fn is_mqtt_connected(&self) -> Result<bool, Error> {
+        let mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        Ok(mqtt_client.is_connected())
+    }
+
+    pub fn get_mqtt_connection(&self) -> Result<Option<MqttSettings>, Error> {
+        let mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        Ok(mqtt_client.settings.clone())
+    }
+
+    pub fn save_mqtt_connection(&self, update: MqttConnectionUpdate) -> Result<(), Error> {
+        let mut mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        let settings = MqttSettings {
+            ip: update.ip,
+            port: update.port,
+            username: update.username.filter(|username| !username.is_empty()),
+            password: update.password.filter(|password| !password.is_empty()),
+            tls: mqtt_client
+                .settings
+                .as_ref()
+                .and_then(|settings| settings.tls.clone()),
+            availability: mqtt_client
+                .settings
+                .as_ref()
+                .and_then(|settings| settings.availability.clone()),
+        };
+        storage.save_mqtt_settings(&settings)?;
+        drop(storage);
+        mqtt_client.settings = Some(settings);
+        let attempt = mqtt_client.start_connect()?;
+        drop(mqtt_client);
+        attempt.wait()
+    }
+
+    pub fn get_mqtt_tls(&self) -> Result<Option<TlsSettings>, Error> {
+        let mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        Ok(mqtt_client
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.tls.clone()))
+    }
+
+    pub fn save_mqtt_tls(&self, tls: Option<TlsSettings>) -> Result<(), Error> {
+        let mut mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        if mqtt_client.settings.is_none() {
+            return Err(Error::NotConfigured("no mqtt settings to add tls to"));
+        }
+        let tls = tls.map(|tls| TlsSettings {
+            ca_file: tls.ca_file.filter(|path| !path.is_empty()),
+            client_cert_file: tls.client_cert_file.filter(|path| !path.is_empty()),
+            client_key_file: tls.client_key_file.filter(|path| !path.is_empty()),
+            verify_hostname: tls.verify_hostname,
+        });
+        let settings = mqtt_client.settings.as_mut().unwrap();
+        settings.tls = tls;
+        storage.save_mqtt_settings(settings)?;
+        drop(storage);
+        let attempt = mqtt_client.start_connect()?;
+        drop(mqtt_client);
+        attempt.wait()
+    }
+
+    pub fn get_mqtt_availability(&self) -> Result<Option<AvailabilitySettings>, Error> {
+        let mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        Ok(mqtt_client
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.availability.clone()))
+    }
+
+    pub fn save_mqtt_availability(
+        &self,
+        availability: Option<AvailabilitySettings>,
+    ) -> Result<(), Error> {
+        let mut mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        if mqtt_client.settings.is_none() {
+            return Err(Error::NotConfigured(
+                "no mqtt settings to add availability to",
+            ));
+        }
+        let availability = availability.filter(|availability| !availability.topic.is_empty());
+        let settings = mqtt_client.settings.as_mut().unwrap();
+        settings.availability = availability;
+        storage.save_mqtt_settings(settings)?;
+        drop(storage);
+        let attempt = mqtt_client.start_connect()?;
+        drop(mqtt_client);
+        attempt.wait()
+    }
+
+    pub fn get_profiles(&self) -> Result<ProfileList, Error> {
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+        Ok(storage.get_profiles())
+    }
+
+    pub fn create_profile(&self, name: &str) -> Result<(), Error> {
+        self.change_profiles(|profiles| profiles.create(name))
+    }
+
+    pub fn rename_profile(&self, name: &str, new_name: &str) -> Result<(), Error> {
+        self.change_profiles(|profiles| profiles.rename(name, new_name))
+    }
+
+    pub fn delete_profile(&self, name: &str) -> Result<(), Error> {
+        self.change_profiles(|profiles| profiles.delete(name))
+    }
+
+    // Turns off the running entries of the old profile before its entries are swapped out,
+    // the new ones start stopped and turn on through the monitor loop like after a restart.
+    pub fn activate_profile(&self, name: &str) -> Result<(), Error> {
+        let mut process_entrys = self
+            .process_entrys
+            .lock()
+            .map_err(|_| Error::Lock("process entrys"))?;
+        let mut mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        let profiles = storage.get_profiles();
+        if profiles.active == name {
+            drop(storage);
+            drop(mqtt_client);
+            drop(process_entrys);
+            // Clicking the checked tray item unchecks it, the rebuilt menu checks it again.
+            self.profiles_changed(&profiles);
+            return Ok(());
+        }
+        if !profiles.profiles.iter().any(|profile| profile.name == name) {
+            return Err(Error::NotFound(format!("unknown profile {}", name)));
+        }
+        turn_off(&mut process_entrys, &mqtt_client);
+        let activated = storage.change_profiles(|profiles| {
+            profiles.active_mut().process_entrys = process_entrys.clone();
+            profiles.activate(name)?;
+            Ok(profiles.active().process_entrys.clone())
+        })?;
+        *process_entrys = activated
+            .into_iter()
+            .map(|mut entry| {
+                entry.is_running = false;
+                entry
+            })
+            .collect();
+
+        let settings = storage.get_active_mqtt_settings();
+        let profiles = storage.get_profiles();
+        drop(storage);
+        if mqtt_client.settings.as_ref() != Some(&settings) {
+            mqtt_client.settings = Some(settings);
+            if let Err(error) = mqtt_client.connect() {
+                warn!("{}", error);
+            }
+        }
+        // Topics the new profile does not use were turned off above, the resync publishes
+        // the state of the ones it does.
+        mqtt_client.request_resync();
+        drop(mqtt_client);
+        drop(process_entrys);
+        info!("activated profile \"{}\"", name);
+        self.profiles_changed(&profiles);
+        Ok(())
+    }
+
+    // Gives the profile its own broker, a copy of the current settings, or makes it use the
+    // global settings again.
+    pub fn set_profile_broker(&self, name: &str, own_broker: bool) -> Result<(), Error> {
+        let mut mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        let settings = own_broker.then(|| mqtt_client.settings.clone()).flatten();
+        if own_broker && settings.is_none() {
+            return Err(Error::NotConfigured("no mqtt settings to copy"));
+        }
+        storage.change_profiles(|profiles| profiles.set_own_broker(name, settings))?;
+        let settings = storage.get_active_mqtt_settings();
+        let profiles = storage.get_profiles();
+        drop(storage);
+        if mqtt_client.settings.as_ref() != Some(&settings) {
+            mqtt_client.settings = Some(settings);
+            if let Err(error) = mqtt_client.connect() {
+                warn!("{}", error);
+            }
+        }
+        drop(mqtt_client);
+        self.profiles_changed(&profiles);
+        Ok(())
+    }
+
+    fn change_profiles(
+        &self,
+        change: impl FnOnce(&mut Profiles) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+        storage.change_profiles(change)?;
+        let profiles = storage.get_profiles();
+        drop(storage);
+        self.profiles_changed(&profiles);
+        Ok(())
+    }
+
+    fn profiles_changed(&self, profiles: &ProfileList) {
+        if let Some(observer) = self.observer() {
+            observer.profiles_changed(profiles);
+        }
+    }
+
+    pub fn export_entries(&self, path: &str, with_mqtt_settings: bool) -> Result<(), Error> {
+        let process_entrys = self.get_process_entrys()?;
+        let mqtt_settings = if with_mqtt_settings {
+            self.get_mqtt_connection()?
+        } else {
+            None
+        };
+        transfer::export(path, &process_entrys, mqtt_settings.as_ref())
+    }
+
+    pub fn import_entries(
+        &self,
+        path: &str,
+        mode: ImportMode,
+        with_mqtt_settings: bool,
+    ) -> Result<ImportSummary, Error> {
+        let mut process_entrys = self
+            .process_entrys
+            .lock()
+            .map_err(|_| Error::Lock("process entrys"))?;
+        let mut mqtt_client = self
+            .mqtt_client
+            .lock()
+            .map_err(|_| Error::Lock("mqtt client"))?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+
+        let import = transfer::import(
+            path,
+            mode,
+            with_mqtt_settings,
+            &process_entrys,
+            mqtt_client.settings.as_ref(),
+        )?;
+        storage.save_process_entrys(&import.process_entrys)?;
+        *process_entrys = import.process_entrys;
+        if let Some(settings) = import.mqtt_settings {
+            storage.save_mqtt_settings(&settings)?;
+            mqtt_client.settings = Some(settings);
+            if let Err(error) = mqtt_client.connect() {
+                warn!("{}", error);
+            }
+        }
+        Ok(import.summary)
+    }
+
+    pub fn get_log_settings(&self) -> Result<LogSettings, Error> {
+        Ok(get_log_settings())
+    }
+
+    pub fn save_log_settings(&self, settings: LogSettings) -> Result<(), Error> {
+        settings.validate().map_err(Error::InvalidInput)?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+        storage.save_log_settings(&settings)?;
+        apply_log_settings(&settings);
+        Ok(())
+    }
+
+    pub fn get_scan_settings(&self) -> Result<ScanSettings, Error> {
+        Ok(self.pacer.settings())
+    }
+
+    pub fn save_scan_settings(&self, settings: ScanSettings) -> Result<(), Error> {
+        settings.validate().map_err(Error::InvalidInput)?;
+        let mut storage = self.storage.lock().map_err(|_| Error::Lock("storage"))?;
+        storage.save_scan_settings(&settings)?;
+        self.pacer.apply(&settings);
+        Ok(())
+    }
+
+    pub fn get_recent_logs(&self, lines: usize) -> Result<Vec<String>, Error> {
+        Ok(recent_log_lines(lines.min(MAX_LOG_LINES)))
+    }
+}
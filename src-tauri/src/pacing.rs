@@ -0,0 +1,319 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+// How long scans stay fast after an entry changed its state in adaptive mode.
+const FAST_PERIOD: Duration = Duration::from_secs(10);
+const FAST_DIVISOR: u32 = 4;
+// Scans are this much slower without entries, on battery or when idle in adaptive mode.
+const SLOW_FACTOR: u32 = 5;
+// Without any entry changing its state for this long, and none running, scans slow down.
+const IDLE_AFTER: Duration = Duration::from_secs(15 * 60);
+// Reading the power supply is cheap but not worth doing on every scan.
+const BATTERY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub const MIN_INTERVAL_MS: u64 = 100;
+pub const MAX_INTERVAL_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScanSettings {
+    // Milliseconds between process scans. With process events on Linux scans happen when
+    // processes change instead.
+    pub scan_interval_ms: u64,
+    // Milliseconds between evaluations of the entries against the last scan.
+    pub evaluation_interval_ms: u64,
+    // Milliseconds between reports of the broker connection state to the GUI.
+    pub connection_state_interval_ms: u64,
+    // Scan faster right after a state change, slower without entries, on battery or when no
+    // entry ran or changed for a while.
+    pub adaptive: bool,
+}
+
+impl ScanSettings {
+    pub const fn new() -> Self {
+        Self {
+            scan_interval_ms: 200,
+            evaluation_interval_ms: 1000,
+            connection_state_interval_ms: 1000,
+            adaptive: false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, interval) in [
+            ("scan", self.scan_interval_ms),
+            ("evaluation", self.evaluation_interval_ms),
+            ("connection state", self.connection_state_interval_ms),
+        ] {
+            if !(MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&interval) {
+                return Err(format!(
+                    "{} interval must be between {} and {} ms",
+                    name, MIN_INTERVAL_MS, MAX_INTERVAL_MS
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pace {
+    Fast,
+    Normal,
+    Slow,
+}
+
+// Hands the engine loops their current intervals.
+pub struct Pacer {
+    settings: Mutex<ScanSettings>,
+    // When an entry last changed its state, or the pacer was created before any did.
+    last_change: Mutex<(Instant, bool)>,
+    has_entries: AtomicBool,
+    // A running entry has to react quickly when its process exits, no matter how long it ran.
+    any_running: AtomicBool,
+    // When the power supply was last read and whether it ran on battery.
+    on_battery: Mutex<Option<(Instant, bool)>>,
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Self {
+            settings: Mutex::new(ScanSettings::new()),
+            last_change: Mutex::new((Instant::now(), false)),
+            has_entries: AtomicBool::new(true),
+            any_running: AtomicBool::new(false),
+            on_battery: Mutex::new(None),
+        }
+    }
+
+    pub fn apply(&self, settings: &ScanSettings) {
+        if let Ok(mut current) = self.settings.lock() {
+            *current = settings.clone();
+        }
+    }
+
+    pub fn settings(&self) -> ScanSettings {
+        self.settings
+            .lock()
+            .map(|settings| settings.clone())
+            .unwrap_or_else(|_| ScanSettings::new())
+    }
+
+    // An entry started, stopped or began waiting for its delay.
+    pub fn state_changed(&self) {
+        if let Ok(mut last_change) = self.last_change.lock() {
+            *last_change = (Instant::now(), true);
+        }
+    }
+
+    pub fn set_has_entries(&self, has_entries: bool) {
+        self.has_entries.store(has_entries, Ordering::Relaxed);
+    }
+
+    pub fn set_any_running(&self, any_running: bool) {
+        self.any_running.store(any_running, Ordering::Relaxed);
+    }
+
+    pub fn scan_interval(&self) -> Duration {
+        let settings = self.settings();
+        self.adapt(settings.scan_interval_ms, &settings)
+            .max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL)
+    }
+
+    pub fn evaluation_interval(&self) -> Duration {
+        let settings = self.settings();
+        self.adapt(settings.evaluation_interval_ms, &settings)
+    }
+
+    pub fn connection_state_interval(&self) -> Duration {
+        let settings = self.settings();
+        self.adapt(settings.connection_state_interval_ms, &settings)
+    }
+
+    fn adapt(&self, interval_ms: u64, settings: &ScanSettings) -> Duration {
+        let pace = if settings.adaptive {
+            self.pace()
+        } else {
+            Pace::Normal
+        };
+        adapt(interval_ms, pace)
+    }
+
+    fn pace(&self) -> Pace {
+        let last_change = self.last_change.lock();
+        if last_change.is_err() {
+            return Pace::Normal;
+        }
+        let (at, changed) = *last_change.unwrap();
+        if changed && at.elapsed() < FAST_PERIOD {
+            return Pace::Fast;
+        }
+        let idle = at.elapsed() >= IDLE_AFTER && !self.any_running.load(Ordering::Relaxed);
+        if idle || !self.has_entries.load(Ordering::Relaxed) || self.is_on_battery() {
+            return Pace::Slow;
+        }
+        Pace::Normal
+    }
+
+    fn is_on_battery(&self) -> bool {
+        let on_battery = self.on_battery.lock();
+        if on_battery.is_err() {
+            return false;
+        }
+        let mut on_battery = on_battery.unwrap();
+        match *on_battery {
+            Some((checked, value)) if checked.elapsed() < BATTERY_CHECK_INTERVAL => value,
+            _ => {
+                let value = read_on_battery();
+                *on_battery = Some((Instant::now(), value));
+                value
+            }
+        }
+    }
+}
+
+fn adapt(interval_ms: u64, pace: Pace) -> Duration {
+    let interval = Duration::from_millis(interval_ms);
+    match pace {
+        Pace::Fast => (interval / FAST_DIVISOR).max(Duration::from_millis(MIN_INTERVAL_MS)),
+        Pace::Normal => interval,
+        Pace::Slow => interval * SLOW_FACTOR,
+    }
+}
+
+// On battery when no mains supply is online and a battery is discharging.
+#[cfg(target_os = "linux")]
+fn read_on_battery() -> bool {
+    use std::fs::{read_dir, read_to_string};
+
+    let supplies = read_dir("/sys/class/power_supply");
+    if supplies.is_err() {
+        return false;
+    }
+    let mut discharging = false;
+    for supply in supplies.unwrap().flatten() {
+        let path = supply.path();
+        let read = |file: &str| {
+            read_to_string(path.join(file))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        match read("type").as_str() {
+            "Mains" if read("online") == "1" => return false,
+            "Battery" if read("status") == "Discharging" => discharging = true,
+            _ => {}
+        }
+    }
+    discharging
+}
+
+// The power supply is only read on Linux, elsewhere adaptive mode ignores it.
+#[cfg(not(target_os = "linux"))]
+fn read_on_battery() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_settings_are_valid() {
+        assert_eq!(ScanSettings::new().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_intervals_out_of_range() {
+        let mut settings = ScanSettings::new();
+        settings.scan_interval_ms = MIN_INTERVAL_MS - 1;
+        assert!(settings.validate().is_err());
+
+        let mut settings = ScanSettings::new();
+        settings.evaluation_interval_ms = MAX_INTERVAL_MS + 1;
+        assert!(settings.validate().is_err());
+
+        let mut settings = ScanSettings::new();
+        settings.connection_state_interval_ms = 0;
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn accepts_interval_bounds() {
+        let mut settings = ScanSettings::new();
+        settings.scan_interval_ms = MIN_INTERVAL_MS;
+        settings.evaluation_interval_ms = MAX_INTERVAL_MS;
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn adapts_interval_to_pace() {
+        assert_eq!(adapt(1000, Pace::Normal), Duration::from_millis(1000));
+        assert_eq!(adapt(1000, Pace::Fast), Duration::from_millis(250));
+        assert_eq!(adapt(1000, Pace::Slow), Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn fast_pace_keeps_minimum_interval() {
+        assert_eq!(
+            adapt(MIN_INTERVAL_MS, Pace::Fast),
+            Duration::from_millis(MIN_INTERVAL_MS)
+        );
+    }
+
+    #[test]
+    fn ignores_pace_when_not_adaptive() {
+        let pacer = Pacer::new();
+        pacer.set_has_entries(false);
+        assert_eq!(pacer.evaluation_interval(), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn speeds_up_after_state_change() {
+        let pacer = Pacer::new();
+        let mut settings = ScanSettings::new();
+        settings.adaptive = true;
+        pacer.apply(&settings);
+        pacer.state_changed();
+        assert_eq!(pacer.evaluation_interval(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn slows_down_without_entries() {
+        let pacer = Pacer::new();
+        let mut settings = ScanSettings::new();
+        settings.adaptive = true;
+        pacer.apply(&settings);
+        pacer.set_has_entries(false);
+        assert_eq!(pacer.evaluation_interval(), Duration::from_millis(5000));
+    }
+
+    fn idle_pacer() -> Pacer {
+        let pacer = Pacer::new();
+        let mut settings = ScanSettings::new();
+        settings.adaptive = true;
+        pacer.apply(&settings);
+        let changed_at = Instant::now().checked_sub(IDLE_AFTER).unwrap();
+        *pacer.last_change.lock().unwrap() = (changed_at, true);
+        *pacer.on_battery.lock().unwrap() = Some((Instant::now(), false));
+        pacer
+    }
+
+    #[test]
+    fn slows_down_when_idle() {
+        let pacer = idle_pacer();
+        assert_eq!(pacer.evaluation_interval(), Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn keeps_pace_while_entry_runs() {
+        let pacer = idle_pacer();
+        pacer.set_any_running(true);
+        assert_eq!(pacer.evaluation_interval(), Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    io,
+    mem::size_of,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    time::{Duration, Instant},
+};
+
+// Process events from the kernel through the netlink proc connector, see
+// include/uapi/linux/cn_proc.h. Older kernels only let processes with CAP_NET_ADMIN listen,
+// without it `open` fails and the caller keeps polling.
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+
+const PROC_EVENT_NONE: u32 = 0;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_COMM: u32 = 0x0000_0200;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+const NLMSG_HEADER_LEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+// Offsets into a received message: netlink header, connector header, then the event.
+const EVENT_WHAT: usize = NLMSG_HEADER_LEN + CN_MSG_LEN;
+const EVENT_DATA: usize = EVENT_WHAT + 16;
+
+// How long `wait` blocks without events, so the caller can check for shut down.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct ProcConnector {
+    socket: OwnedFd,
+}
+
+impl ProcConnector {
+    pub fn open() -> Result<Self, String> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_CONNECTOR,
+            )
+        };
+        if fd < 0 {
+            return Err(format!(
+                "failed to create netlink socket: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        let connector = Self {
+            socket: unsafe { OwnedFd::from_raw_fd(fd) },
+        };
+
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = CN_IDX_PROC;
+        let result = unsafe {
+            libc::bind(
+                fd,
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(format!(
+                "failed to bind proc connector: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        connector.set_receive_timeout()?;
+        connector.subscribe()?;
+        Ok(connector)
+    }
+
+    // Blocks until a process executed a new program, exited or was renamed. Returns false
+    // when the timeout passed without such an event.
+    pub fn wait(&self) -> Result<bool, String> {
+        let mut buffer = [0u8; 4096];
+        let received = unsafe {
+            libc::recv(
+                self.socket.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                0,
+            )
+        };
+        if received < 0 {
+            let error = io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::EAGAIN) | Some(libc::EINTR) => Ok(false),
+                // Events were dropped because we fell behind, something may have changed.
+                Some(libc::ENOBUFS) => Ok(true),
+                _ => Err(format!("failed to receive process event: {}", error)),
+            };
+        }
+        let message = &buffer[..received as usize];
+        if message.len() < EVENT_DATA + 8 {
+            return Ok(false);
+        }
+        let pid = read_u32(message, EVENT_DATA);
+        let tgid = read_u32(message, EVENT_DATA + 4);
+        Ok(match read_u32(message, EVENT_WHAT) {
+            PROC_EVENT_EXEC | PROC_EVENT_COMM => true,
+            // Threads exiting are reported as well, only the main thread ends the process.
+            PROC_EVENT_EXIT => pid == tgid,
+            _ => false,
+        })
+    }
+
+    fn set_receive_timeout(&self) -> Result<(), String> {
+        let timeout = libc::timeval {
+            tv_sec: RECEIVE_TIMEOUT.as_secs() as libc::time_t,
+            tv_usec: 0,
+        };
+        let result = unsafe {
+            libc::setsockopt(
+                self.socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(format!(
+                "failed to set receive timeout: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    // Asks the kernel to start sending events and checks its acknowledgement, which carries
+    // the error when we are not allowed to listen.
+    fn subscribe(&self) -> Result<(), String> {
+        let mut message = [0u8; NLMSG_HEADER_LEN + CN_MSG_LEN + 4];
+        let length = message.len() as u32;
+        write_u32(&mut message, 0, length);
+        write_u16(&mut message, 4, libc::NLMSG_DONE as u16);
+        write_u32(&mut message, 12, std::process::id());
+        write_u32(&mut message, NLMSG_HEADER_LEN, CN_IDX_PROC);
+        write_u32(&mut message, NLMSG_HEADER_LEN + 4, CN_VAL_PROC);
+        write_u16(&mut message, NLMSG_HEADER_LEN + 16, 4);
+        write_u32(
+            &mut message,
+            NLMSG_HEADER_LEN + CN_MSG_LEN,
+            PROC_CN_MCAST_LISTEN,
+        );
+        let sent = unsafe {
+            libc::send(
+                self.socket.as_raw_fd(),
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+            )
+        };
+        if sent < 0 {
+            return Err(format!(
+                "failed to subscribe to process events: {}",
+                io::Error::last_os_error()
+            ));
+        }
+
+        // Events of other listeners may arrive before the acknowledgement.
+        let deadline = Instant::now() + RECEIVE_TIMEOUT;
+        let mut buffer = [0u8; 4096];
+        loop {
+            if Instant::now() > deadline {
+                return Err("no acknowledgement for process events".to_string());
+            }
+            let received = unsafe {
+                libc::recv(
+                    self.socket.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                    0,
+                )
+            };
+            if received < 0 {
+                return Err(format!(
+                    "no acknowledgement for process events: {}",
+                    io::Error::last_os_error()
+                ));
+            }
+            let message = &buffer[..received as usize];
+            if message.len() < EVENT_DATA + 4 || read_u32(message, EVENT_WHAT) != PROC_EVENT_NONE {
+                continue;
+            }
+            let error = read_u32(message, EVENT_DATA);
+            if error != 0 {
+                return Err(format!(
+                    "failed to subscribe to process events: {}",
+                    io::Error::from_raw_os_error(error as i32)
+                ));
+            }
+            return Ok(());
+        }
+    }
+}
+
+// Netlink messages use the byte order of the host.
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u32(buffer: &mut [u8], offset: usize, value: u32) {
+    buffer[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+}
+
+fn write_u16(buffer: &mut [u8], offset: usize, value: u16) {
+    buffer[offset..offset + 2].copy_from_slice(&value.to_ne_bytes());
+}
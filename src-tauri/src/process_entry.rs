@@ -1,10 +1,206 @@
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    error::Error,
+    matcher::{MatchMode, MatchTarget, Matcher},
+    process_info::ProcessInfo,
+};
+
+pub const DEFAULT_QOS: i32 = 1;
+pub const DEFAULT_RETAIN: bool = true;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProcessEntry {
+    pub id: String,
+    // Decided by the scans, a stored value could publish a stale on value at startup.
+    #[serde(skip)]
     pub is_running: bool,
     pub name: String,
+    pub match_mode: MatchMode,
+    pub match_target: MatchTarget,
+    pub priority: i32,
+    // Seconds a process has to be present before the entry turns on.
+    pub on_delay: u64,
+    // Seconds a process has to be gone before the entry turns off.
+    pub off_delay: u64,
+    pub actions: Vec<ProcessAction>,
+    // When the process started to differ from `is_running`.
+    #[serde(skip)]
+    pub pending_since: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunningState {
+    Stopped,
+    Starting,
+    Running,
+    Stopping,
+}
+
+pub fn new_entry_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+impl ProcessEntry {
+    pub fn new() -> Self {
+        Self {
+            id: new_entry_id(),
+            is_running: false,
+            name: String::new(),
+            match_mode: MatchMode::Exact,
+            match_target: MatchTarget::Name,
+            priority: 0,
+            on_delay: 0,
+            off_delay: 0,
+            actions: vec![ProcessAction::new()],
+            pending_since: None,
+        }
+    }
+
+    pub fn matches(&self, matcher: &Matcher, processes: &[ProcessInfo]) -> bool {
+        processes.iter().any(|process| {
+            process
+                .field(self.match_target)
+                .is_some_and(|field| matcher.is_match(field))
+        })
+    }
+
+    pub fn observe(&mut self, is_present: bool, now: Instant) {
+        if is_present == self.is_running {
+            self.pending_since = None;
+            return;
+        }
+        let since = *self.pending_since.get_or_insert(now);
+        let delay = if is_present {
+            self.on_delay
+        } else {
+            self.off_delay
+        };
+        if now.duration_since(since) >= Duration::from_secs(delay) {
+            self.is_running = is_present;
+            self.pending_since = None;
+        }
+    }
+
+    pub fn running_state(&self) -> RunningState {
+        match (self.is_running, self.pending_since.is_some()) {
+            (false, false) => RunningState::Stopped,
+            (false, true) => RunningState::Starting,
+            (true, false) => RunningState::Running,
+            (true, true) => RunningState::Stopping,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessAction {
     pub topic: String,
     pub value: String,
     pub off_value: String,
+    pub qos: i32,
+    pub retain: bool,
+}
+
+impl ProcessAction {
+    pub fn new() -> Self {
+        Self {
+            topic: String::new(),
+            value: String::new(),
+            off_value: String::new(),
+            qos: DEFAULT_QOS,
+            retain: DEFAULT_RETAIN,
+        }
+    }
+}
+
+pub fn is_valid_qos(qos: i32) -> bool {
+    (0..=2).contains(&qos)
+}
+
+// Checks what the monitor loop would otherwise only log, before an entry gets saved.
+pub fn validate_entry(
+    name: &str,
+    match_mode: MatchMode,
+    actions: &[ProcessAction],
+) -> Result<(), Error> {
+    if let Some(action) = actions.iter().find(|action| !is_valid_qos(action.qos)) {
+        return Err(Error::InvalidInput(format!(
+            "invalid qos {} for topic \"{}\", expected 0, 1 or 2",
+            action.qos, action.topic
+        )));
+    }
+    Matcher::new(match_mode, name).map_err(Error::InvalidInput)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(on_delay: u64, off_delay: u64) -> ProcessEntry {
+        let mut entry = ProcessEntry::new();
+        entry.on_delay = on_delay;
+        entry.off_delay = off_delay;
+        entry
+    }
+
+    #[test]
+    fn starts_after_on_delay() {
+        let mut entry = entry(5, 0);
+        let start = Instant::now();
+        entry.observe(true, start);
+        assert_eq!(entry.running_state(), RunningState::Starting);
+        entry.observe(true, start + Duration::from_secs(4));
+        assert_eq!(entry.running_state(), RunningState::Starting);
+        entry.observe(true, start + Duration::from_secs(5));
+        assert_eq!(entry.running_state(), RunningState::Running);
+        assert!(entry.is_running);
+    }
+
+    #[test]
+    fn stops_after_off_delay() {
+        let mut entry = entry(0, 10);
+        entry.is_running = true;
+        let start = Instant::now();
+        entry.observe(false, start);
+        assert_eq!(entry.running_state(), RunningState::Stopping);
+        entry.observe(false, start + Duration::from_secs(9));
+        assert_eq!(entry.running_state(), RunningState::Stopping);
+        entry.observe(false, start + Duration::from_secs(10));
+        assert_eq!(entry.running_state(), RunningState::Stopped);
+        assert!(!entry.is_running);
+    }
+
+    #[test]
+    fn flap_within_delay_cancels_pending_state() {
+        let mut entry = entry(5, 5);
+        let start = Instant::now();
+        entry.observe(true, start);
+        entry.observe(false, start + Duration::from_secs(2));
+        assert_eq!(entry.running_state(), RunningState::Stopped);
+        // The delay starts over once the process is back.
+        entry.observe(true, start + Duration::from_secs(3));
+        entry.observe(true, start + Duration::from_secs(7));
+        assert_eq!(entry.running_state(), RunningState::Starting);
+        entry.observe(true, start + Duration::from_secs(8));
+        assert_eq!(entry.running_state(), RunningState::Running);
+
+        entry.observe(false, start + Duration::from_secs(9));
+        entry.observe(true, start + Duration::from_secs(10));
+        assert_eq!(entry.running_state(), RunningState::Running);
+    }
+
+    #[test]
+    fn zero_delay_switches_immediately() {
+        let mut entry = entry(0, 0);
+        let now = Instant::now();
+        entry.observe(true, now);
+        assert_eq!(entry.running_state(), RunningState::Running);
+        entry.observe(false, now);
+        assert_eq!(entry.running_state(), RunningState::Stopped);
+    }
 }
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use sysinfo::{Process, Users};
+
+use crate::matcher::MatchTarget;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub exe: Option<String>,
+    pub cmd: String,
+    pub user: Option<String>,
+    // Seconds since the unix epoch.
+    pub start_time: u64,
+    pub cpu_usage: f32,
+    // Resident memory in bytes.
+    pub memory: u64,
+}
+
+impl ProcessInfo {
+    pub fn new(process: &Process, users: &Users) -> Option<Self> {
+        let name = process.name().to_str()?.to_string();
+        let exe = process.exe().map(|exe| exe.to_string_lossy().to_string());
+        let cmd = process
+            .cmd()
+            .iter()
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let user = process
+            .user_id()
+            .and_then(|user_id| users.get_user_by_id(user_id))
+            .map(|user| user.name().to_string());
+        Some(Self {
+            pid: process.pid().as_u32(),
+            name,
+            exe,
+            cmd,
+            user,
+            start_time: process.start_time(),
+            cpu_usage: process.cpu_usage(),
+            memory: process.memory(),
+        })
+    }
+
+    pub fn field(&self, target: MatchTarget) -> Option<&str> {
+        match target {
+            MatchTarget::Name => Some(&self.name),
+            MatchTarget::ExePath => self.exe.as_deref(),
+            MatchTarget::CommandLine => Some(&self.cmd),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{
+    error::Error,
+    mqtt_settings::MqttSettings,
+    process_entry::{validate_entry, ProcessEntry},
+};
+
+pub const DEFAULT_PROFILE: &str = "Default";
+
+// A named set of entries, only the entries of the active profile are monitored.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub process_entrys: Vec<ProcessEntry>,
+    // Used instead of the global broker settings while the profile is active.
+    pub mqtt_settings: Option<MqttSettings>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiles {
+    active: usize,
+    profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub own_broker: bool,
+}
+
+// What the UI and the tray show of the profiles.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ProfileList {
+    pub active: String,
+    pub profiles: Vec<ProfileInfo>,
+}
+
+impl Profiles {
+    pub fn new(process_entrys: Vec<ProcessEntry>) -> Self {
+        Self {
+            active: 0,
+            profiles: vec![Profile {
+                name: DEFAULT_PROFILE.to_string(),
+                process_entrys,
+                mqtt_settings: None,
+            }],
+        }
+    }
+
+    // Checks what can not be expressed in the stored format, names and entry ids have to be
+    // unique and the active profile has to exist.
+    pub fn from_profiles(active: &str, profiles: Vec<Profile>) -> Result<Self, String> {
+        let mut names = HashSet::new();
+        let mut ids = HashSet::new();
+        for profile in &profiles {
+            if profile.name.trim().is_empty() || !names.insert(profile.name.as_str()) {
+                return Err(format!(
+                    "missing or duplicate profile name \"{}\"",
+                    profile.name
+                ));
+            }
+            for entry in &profile.process_entrys {
+                if entry.id.is_empty() || !ids.insert(entry.id.as_str()) {
+                    return Err(format!("missing or duplicate id \"{}\"", entry.id));
+                }
+            }
+        }
+        let active_index = profiles.iter().position(|profile| profile.name == active);
+        if active_index.is_none() {
+            return Err(format!("unknown active profile \"{}\"", active));
+        }
+        Ok(Self {
+            active: active_index.unwrap(),
+            profiles,
+        })
+    }
+
+    // Entries saved through the app are always valid, edits from elsewhere are checked
+    // before they replace the running config.
+    pub fn validate_entries(&self) -> Result<(), String> {
+        for entry in self
+            .profiles
+            .iter()
+            .flat_map(|profile| &profile.process_entrys)
+        {
+            if let Err(error) = validate_entry(&entry.name, entry.match_mode, &entry.actions) {
+                return Err(format!("entry \"{}\": {}", entry.name, error));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn active(&self) -> &Profile {
+        &self.profiles[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Profile {
+        &mut self.profiles[self.active]
+    }
+
+    pub fn list(&self) -> ProfileList {
+        ProfileList {
+            active: self.active().name.clone(),
+            profiles: self
+                .profiles
+                .iter()
+                .map(|profile| ProfileInfo {
+                    name: profile.name.clone(),
+                    own_broker: profile.mqtt_settings.is_some(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn create(&mut self, name: &str) -> Result<(), Error> {
+        let name = self.check_new_name(name)?;
+        self.profiles.push(Profile {
+            name,
+            process_entrys: vec![],
+            mqtt_settings: None,
+        });
+        Ok(())
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), Error> {
+        let index = self.index(name)?;
+        let new_name = self.check_new_name(new_name)?;
+        self.profiles[index].name = new_name;
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), Error> {
+        let index = self.index(name)?;
+        if index == self.active {
+            return Err(Error::InvalidInput(
+                "the active profile can not be deleted".to_string(),
+            ));
+        }
+        self.profiles.remove(index);
+        if index < self.active {
+            self.active -= 1;
+        }
+        Ok(())
+    }
+
+    pub fn activate(&mut self, name: &str) -> Result<(), Error> {
+        self.active = self.index(name)?;
+        Ok(())
+    }
+
+    // Gives a profile its own broker, starting from the given settings, or removes it again.
+    pub fn set_own_broker(
+        &mut self,
+        name: &str,
+        mqtt_settings: Option<MqttSettings>,
+    ) -> Result<(), Error> {
+        let index = self.index(name)?;
+        self.profiles[index].mqtt_settings = mqtt_settings;
+        Ok(())
+    }
+
+    fn index(&self, name: &str) -> Result<usize, Error> {
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == name);
+        if index.is_none() {
+            return Err(Error::NotFound(format!("unknown profile {}", name)));
+        }
+        Ok(index.unwrap())
+    }
+
+    fn check_new_name(&self, name: &str) -> Result<String, Error> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(Error::InvalidInput(
+                "profile name must not be empty".to_string(),
+            ));
+        }
+        if self.profiles.iter().any(|profile| profile.name == name) {
+            return Err(Error::InvalidInput(format!(
+                "a profile named \"{}\" already exists",
+                name
+            )));
+        }
+        Ok(name.to_string())
+    }
+}
@@ -1,29 +1,116 @@
-use std::{
-    collections::HashSet,
-    env,
-    sync::{Arc, Mutex},
-    thread,
-    time::Duration,
-};
+#[cfg(unix)]
+use std::path::Path;
+use std::{collections::HashMap, env, sync::Arc, thread};
 
+use log::error;
 use tauri::{
-    menu::{Menu, MenuItem},
+    menu::{CheckMenuItem, IsMenuItem, Menu, MenuItem, Submenu},
     tray::TrayIconBuilder,
-    App, Emitter, Manager,
+    App, AppHandle, Emitter, Manager, Wry,
 };
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
 
+#[cfg(unix)]
+use crate::control_socket::ControlSocket;
 use crate::{
-    fetch_processes, log, logger::set_log_path, mqtt::MqttClient, process_entry::ProcessEntry,
-    storage::Storage, AUTO_START,
+    engine::{Engine, EngineObserver},
+    process_entry::RunningState,
+    profiles::ProfileList,
+    AUTO_START,
 };
 
-pub fn setup(
-    app: &mut App,
-    storage: Arc<Mutex<Storage>>,
-    process_entrys: Arc<Mutex<Vec<ProcessEntry>>>,
-    mqtt_client: Arc<Mutex<MqttClient>>,
-    processes: Arc<Mutex<HashSet<String>>>,
-) {
+const TRAY_ID: &str = "main";
+const PROFILE_ITEM_PREFIX: &str = "profile:";
+
+impl EngineObserver for AppHandle {
+    fn running_states(&self, running_states: &HashMap<String, RunningState>) {
+        if self.emit("running_states", running_states).is_err() {
+            error!("failed to emit running_states");
+        }
+    }
+
+    fn mqtt_connection_state(&self, connected: bool) {
+        if self.emit("mqtt_connection_state", connected).is_err() {
+            error!("failed to emit mqtt_connection_state");
+        }
+    }
+
+    fn mqtt_connection_failed(&self, message: &str) {
+        if self.emit("mqtt_connection_failed", message).is_err() {
+            error!("failed to emit mqtt_connection_failed");
+        }
+    }
+
+    // Shown as a dialog, the webview may not be listening yet when this happens on startup.
+    fn storage_recovered(&self, message: &str) {
+        self.dialog()
+            .message(message)
+            .title("Configuration restored")
+            .kind(MessageDialogKind::Warning)
+            .show(|_| {});
+    }
+
+    fn config_reloaded(&self) {
+        if self.emit("config_reloaded", ()).is_err() {
+            error!("failed to emit config_reloaded");
+        }
+    }
+
+    fn config_invalid(&self, message: &str) {
+        self.dialog()
+            .message(message)
+            .title("Configuration not reloaded")
+            .kind(MessageDialogKind::Warning)
+            .show(|_| {});
+    }
+
+    fn profiles_changed(&self, profiles: &ProfileList) {
+        if self.emit("profiles_changed", profiles).is_err() {
+            error!("failed to emit profiles_changed");
+        }
+        let tray = self.tray_by_id(TRAY_ID);
+        if tray.is_none() {
+            error!("failed to get system tray");
+            return;
+        }
+        let menu = build_tray_menu(self, profiles);
+        if menu.is_err() {
+            error!("failed to create menu");
+            return;
+        }
+        if tray.unwrap().set_menu(Some(menu.unwrap())).is_err() {
+            error!("failed to update tray menu");
+        }
+    }
+}
+
+// Open, a submenu to switch profiles and quit. Rebuilt whenever the profiles change.
+fn build_tray_menu(app: &AppHandle, profiles: &ProfileList) -> tauri::Result<Menu<Wry>> {
+    let open_item = MenuItem::with_id(app, "open", "Open", true, None::<&str>)?;
+    let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let profile_items = profiles
+        .profiles
+        .iter()
+        .map(|profile| {
+            CheckMenuItem::with_id(
+                app,
+                format!("{}{}", PROFILE_ITEM_PREFIX, profile.name),
+                &profile.name,
+                true,
+                profile.name == profiles.active,
+                None::<&str>,
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let profile_items: Vec<&dyn IsMenuItem<Wry>> = profile_items
+        .iter()
+        .map(|item| item as &dyn IsMenuItem<Wry>)
+        .collect();
+    let profiles_menu = Submenu::with_items(app, "Profiles", true, &profile_items)?;
+    Menu::with_items(app, &[&open_item, &profiles_menu, &quit_item])
+}
+
+pub fn setup(app: &mut App, engine: Engine) {
     let app_data_dir = app.path().app_data_dir();
     if app_data_dir.is_err() {
         return;
@@ -33,79 +120,48 @@ pub fn setup(
         return;
     }
     let path = path.unwrap();
-    {
-        let storage_lock = storage.lock();
-        if storage_lock.is_err() {
-            return;
-        }
-        storage_lock.unwrap().set_path(path.clone());
-        set_log_path(path);
-    }
-    log("test");
+    engine.load(path.clone());
     let args: Vec<String> = env::args().collect();
     if args.contains(&AUTO_START.to_owned()) {
         let window = app.get_webview_window("main");
         if window.is_none() {
-            log("main window not found");
+            error!("main window not found");
             return;
         } else {
             if let Err(_) = window.unwrap().hide() {
-                log("failed to hide main window");
+                error!("failed to hide main window");
             }
         }
     }
 
-    let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>);
-    if quit_item.is_err() {
-        log("failed to create quit item");
-        return;
-    }
-    let quit_item = quit_item.unwrap();
-
-    let open_item = MenuItem::with_id(app, "open", "Open", true, None::<&str>);
-    if open_item.is_err() {
-        log("failed to create open item");
+    let profiles = engine.get_profiles();
+    if profiles.is_err() {
+        error!("failed to get profiles");
         return;
     }
-    let open_item = open_item.unwrap();
-
-    let menu = Menu::with_items(app, &[&open_item, &quit_item]);
+    let menu = build_tray_menu(app.handle(), &profiles.unwrap());
     if menu.is_err() {
-        log("failed to create menu");
+        error!("failed to create menu");
         return;
     }
     let menu = menu.unwrap();
 
-    let storage_system_tray_clone = storage.clone();
-    let process_entrys_system_tray_clone = process_entrys.clone();
+    let engine_system_tray_clone = engine.clone();
 
     let icon = app.default_window_icon();
     if icon.is_none() {
-        log("failed to get default window icon");
+        error!("failed to get default window icon");
         return;
     }
     let icon = icon.unwrap();
 
-    let result = TrayIconBuilder::new()
+    let result = TrayIconBuilder::with_id(TRAY_ID)
         .menu(&menu)
         .show_menu_on_left_click(false)
         .icon(icon.clone())
         .on_menu_event(move |app, event| match event.id.as_ref() {
             "quit" => {
-                let storage_system_tray_clone = storage_system_tray_clone.lock();
-                if storage_system_tray_clone.is_err() {
-                    log("failed to lock storage system tray");
-                    return;
-                }
-                let process_entrys_system_tray_clone = process_entrys_system_tray_clone.lock();
-                if process_entrys_system_tray_clone.is_err() {
-                    log("failed to lock process entrys system tray");
-                    return;
-                }
-                storage_system_tray_clone
-                    .unwrap()
-                    .save_process_entrys(&process_entrys_system_tray_clone.unwrap());
-
+                engine_system_tray_clone.shutdown();
                 app.exit(0);
             }
             "open" => {
@@ -113,121 +169,46 @@ pub fn setup(
                 if window.is_some() {
                     let result = window.unwrap().show();
                     if result.is_err() {
-                        log("failed to show window");
+                        error!("failed to show window");
                     }
                 } else {
-                    log("failed to get webview window");
+                    error!("failed to get webview window");
+                }
+            }
+            id => {
+                if let Some(name) = id.strip_prefix(PROFILE_ITEM_PREFIX) {
+                    // Switching waits for the off values to be delivered, which must not
+                    // block the event loop.
+                    let engine = engine_system_tray_clone.clone();
+                    let name = name.to_string();
+                    thread::spawn(move || {
+                        if let Err(error) = engine.activate_profile(&name) {
+                            error!("{}", error);
+                        }
+                    });
                 }
             }
-            _ => {}
         })
         .build(app);
 
     if result.is_err() {
-        log("failed to build system tray");
+        error!("failed to build system tray");
     }
 
-    {
-        let storage = storage.lock();
-        let process_entrys = process_entrys.lock();
-        let mqtt_client = mqtt_client.lock();
-
-        if storage.is_err() {
-            log("failed to lock storage");
-            return;
-        }
-        if process_entrys.is_err() {
-            log("failed to lock process entrys");
-            return;
-        }
-        if mqtt_client.is_err() {
-            log("failed to lock mqtt client");
-            return;
-        }
-
-        let storage = storage.unwrap();
-        let mut mqtt_client = mqtt_client.unwrap();
-
-        let saved_process_entrys = storage.get_saved_process_entrys();
-        *process_entrys.unwrap() = saved_process_entrys;
-
-        let saved_mqtt_settings = storage.get_mqtt_settings();
-        mqtt_client.settings = Some(saved_mqtt_settings);
-        mqtt_client.connect();
+    let signal_app_handle = app.handle().clone();
+    let result = ctrlc::set_handler(move || signal_app_handle.exit(0));
+    if result.is_err() {
+        error!("failed to register signal handler");
     }
 
-    let running_states_app_handle = app.handle().clone();
-    let mqtt_connction_state_app_handle = app.handle().clone();
-
-    let mqtt_client_connected = mqtt_client.clone();
-    let processes_clone = processes.clone();
-
-    thread::spawn(|| fetch_processes(processes));
-    thread::spawn(move || loop {
-        {
-            let process_entrys = process_entrys.lock();
-            if process_entrys.is_err() {
-                log("failed to lock process entrys");
-                continue;
-            }
-            let mut process_entrys = process_entrys.unwrap();
-
-            let mqtt_client = mqtt_client.lock();
-            if mqtt_client.is_err() {
-                log("failed to lock mqtt client");
-                continue;
-            }
-            let mqtt_client = mqtt_client.unwrap();
-
-            let processes = processes_clone.lock();
-            if processes.is_err() {
-                log("failed to lock processes");
-                continue;
-            }
-            let processes = processes.unwrap();
-
-            for entry in process_entrys.iter_mut() {
-                if processes.contains(&entry.name) && !entry.is_running {
-                    entry.is_running = true;
-                    mqtt_client.publish(&entry.topic, &entry.value);
-                    mqtt_client.publish(
-                        &"tgn/esp_3/neopixel/brightness".to_string(),
-                        &"150".to_string(),
-                    );
-                } else if !processes.contains(&entry.name) && entry.is_running {
-                    entry.is_running = false;
-                    mqtt_client.publish(&entry.topic, &entry.off_value);
-                }
-            }
-
-            let running_states: Vec<bool> = (*process_entrys)
-                .iter()
-                .map(|entry| entry.is_running)
-                .collect();
-
-            let error = running_states_app_handle.emit("running_states", running_states);
-            if error.is_err() {
-                log("failed to emit running_states");
-                continue;
-            }
-        }
-        thread::sleep(Duration::from_secs(1));
-    });
-
-    thread::spawn(move || loop {
-        {
-            let mqtt_client = mqtt_client_connected.lock();
-            if mqtt_client.is_err() {
-                log("failed to lock mqtt client");
-                continue;
-            }
-            let connected = mqtt_client.unwrap().is_connected();
-            let error = mqtt_connction_state_app_handle.emit("mqtt_connection_state", connected);
-            if error.is_err() {
-                log("failed to emit mqtt_connection_state");
-                continue;
-            }
+    let observers: Vec<Arc<dyn EngineObserver>> = vec![Arc::new(app.handle().clone())];
+    #[cfg(unix)]
+    let observers = {
+        let mut observers = observers;
+        if let Some(control_socket) = ControlSocket::start(&engine, Path::new(&path)) {
+            observers.push(control_socket);
         }
-        thread::sleep(Duration::from_secs(1));
-    });
+        observers
+    };
+    engine.start(Arc::new(observers));
 }
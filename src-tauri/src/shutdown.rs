@@ -0,0 +1,70 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::error;
+
+use crate::{arbiter::winning_actions, mqtt::MqttClient, process_entry::ProcessEntry};
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+pub fn is_shut_down() -> bool {
+    SHUT_DOWN.load(Ordering::SeqCst)
+}
+
+// Turns off every running entry. Only the first call has an effect, so it is safe to call from
+// every exit path. Entries are not saved here, every operation already did and saving again
+// would overwrite edits made on disk since the last reload.
+pub fn shutdown(
+    process_entrys: &Arc<Mutex<Vec<ProcessEntry>>>,
+    mqtt_client: &Arc<Mutex<MqttClient>>,
+) {
+    if SHUT_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let process_entrys = process_entrys.lock();
+    if process_entrys.is_err() {
+        error!("failed to lock process entrys");
+        return;
+    }
+    let mut process_entrys = process_entrys.unwrap();
+    let mqtt_client = mqtt_client.lock();
+    if mqtt_client.is_err() {
+        error!("failed to lock mqtt client");
+        return;
+    }
+    let mut mqtt_client = mqtt_client.unwrap();
+
+    turn_off(&mut process_entrys, &mqtt_client);
+    mqtt_client.disconnect();
+}
+
+// Publishes the off value of every topic owned by a running entry and waits until they are
+// delivered, or the delivery timeout passes.
+pub fn turn_off(process_entrys: &mut [ProcessEntry], mqtt_client: &MqttClient) {
+    let mut tokens = vec![];
+    for action in winning_actions(process_entrys).into_values() {
+        if let Some(token) =
+            mqtt_client.publish(&action.topic, &action.off_value, action.qos, action.retain)
+        {
+            tokens.push(token);
+        }
+    }
+    for entry in process_entrys.iter_mut() {
+        entry.is_running = false;
+        entry.pending_since = None;
+    }
+    let deadline = Instant::now() + DELIVERY_TIMEOUT;
+    for token in tokens {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if token.wait_for(remaining).is_err() {
+            error!("failed to deliver off value");
+        }
+    }
+}
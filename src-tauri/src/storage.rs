@@ -1,18 +1,277 @@
 use std::{
-    fs::{create_dir_all, read, write},
-    path::PathBuf,
+    collections::HashMap,
+    fs::{copy, create_dir_all, read, read_dir, read_to_string, remove_file, rename, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
 };
 
-use crate::{log, mqtt_settings::MqttSettings, process_entry::ProcessEntry};
+use chrono::Utc;
+
+use log::{error, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    credentials::{obfuscate_hex, reveal_hex},
+    error::Error,
+    legacy::{MqttSettingsV0, ProcessEntryV0},
+    logger::LogSettings,
+    mqtt_settings::{AvailabilitySettings, MqttSettings, TlsSettings},
+    pacing::ScanSettings,
+    process_entry::ProcessEntry,
+    profiles::{Profile, ProfileList, Profiles, DEFAULT_PROFILE},
+};
+
+// Upgrades a stored JSON document by one version. The version of a file is one more than
+// the number of migrations it still needs, so adding a migration bumps the current version.
+type Migration = fn(&mut Value);
+
+const PROCESS_ENTRYS_MIGRATIONS: &[Migration] = &[entries_into_default_profile];
+const MQTT_SETTINGS_MIGRATIONS: &[Migration] = &[];
+const LOG_SETTINGS_MIGRATIONS: &[Migration] = &[];
+const SCAN_SETTINGS_MIGRATIONS: &[Migration] = &[];
+
+fn current_version(migrations: &[Migration]) -> u32 {
+    migrations.len() as u32 + 1
+}
+
+// Version 2 groups the entries into named profiles.
+fn entries_into_default_profile(value: &mut Value) {
+    let entries = value
+        .as_object_mut()
+        .and_then(|value| value.remove("entries"))
+        .unwrap_or_else(|| json!([]));
+    value["active_profile"] = json!(DEFAULT_PROFILE);
+    value["profiles"] = json!([{ "name": DEFAULT_PROFILE, "entries": entries }]);
+}
+
+// The profiles and their entries, stored in the process entrys file.
+#[derive(Serialize, Deserialize)]
+struct StoredProfiles {
+    version: u32,
+    active_profile: String,
+    profiles: Vec<StoredProfile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredProfile {
+    name: String,
+    entries: Vec<ProcessEntry>,
+    #[serde(default)]
+    mqtt_settings: Option<StoredBroker>,
+}
+
+// Deserializes only profiles that pass `Profiles::from_profiles`, so inconsistent ones are
+// treated like a damaged file.
+#[derive(Deserialize)]
+#[serde(try_from = "StoredProfiles")]
+struct ValidProfiles(Profiles);
+
+impl TryFrom<StoredProfiles> for ValidProfiles {
+    type Error = String;
+
+    fn try_from(stored: StoredProfiles) -> Result<Self, Self::Error> {
+        let profiles = stored
+            .profiles
+            .into_iter()
+            .map(|profile| Profile {
+                name: profile.name,
+                process_entrys: profile.entries,
+                mqtt_settings: profile.mqtt_settings.map(MqttSettings::from),
+            })
+            .collect();
+        Profiles::from_profiles(&stored.active_profile, profiles).map(ValidProfiles)
+    }
+}
+
+impl From<&Profiles> for StoredProfiles {
+    fn from(profiles: &Profiles) -> Self {
+        Self {
+            version: current_version(PROCESS_ENTRYS_MIGRATIONS),
+            active_profile: profiles.active().name.clone(),
+            profiles: profiles
+                .profiles()
+                .iter()
+                .map(|profile| StoredProfile {
+                    name: profile.name.clone(),
+                    entries: profile.process_entrys.clone(),
+                    mqtt_settings: profile.mqtt_settings.as_ref().map(StoredBroker::from),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredLogSettings {
+    version: u32,
+    #[serde(flatten)]
+    settings: LogSettings,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredScanSettings {
+    version: u32,
+    #[serde(flatten)]
+    settings: ScanSettings,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredMqttSettings {
+    version: u32,
+    #[serde(flatten)]
+    broker: StoredBroker,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredBroker {
+    ip: String,
+    port: u16,
+    username: Option<String>,
+    // Obfuscated, see `credentials`.
+    password: Option<String>,
+    tls: Option<TlsSettings>,
+    availability: Option<AvailabilitySettings>,
+}
+
+impl From<&MqttSettings> for StoredMqttSettings {
+    fn from(settings: &MqttSettings) -> Self {
+        Self {
+            version: current_version(MQTT_SETTINGS_MIGRATIONS),
+            broker: StoredBroker::from(settings),
+        }
+    }
+}
+
+impl From<StoredMqttSettings> for MqttSettings {
+    fn from(settings: StoredMqttSettings) -> Self {
+        Self::from(settings.broker)
+    }
+}
+
+impl From<&MqttSettings> for StoredBroker {
+    fn from(settings: &MqttSettings) -> Self {
+        Self {
+            ip: settings.ip.clone(),
+            port: settings.port,
+            username: settings.username.clone(),
+            password: settings.password.as_deref().map(obfuscate_hex),
+            tls: settings.tls.clone(),
+            availability: settings.availability.clone(),
+        }
+    }
+}
+
+impl From<StoredBroker> for MqttSettings {
+    fn from(settings: StoredBroker) -> Self {
+        let password = settings.password.as_deref().and_then(reveal_hex);
+        Self {
+            ip: settings.ip,
+            port: settings.port,
+            username: settings.username,
+            password,
+            tls: settings.tls,
+            availability: settings.availability,
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_string_pretty(value)
+        .map_err(|error| Error::Storage(format!("failed to serialize config: {}", error)))
+}
+
+fn from_json<T: DeserializeOwned>(data: &str, migrations: &[Migration]) -> Result<T, String> {
+    let mut value: Value = serde_json::from_str(data).map_err(|error| error.to_string())?;
+    let version = value.get("version").and_then(Value::as_u64);
+    if version.is_none() {
+        return Err("missing version".to_string());
+    }
+    let version = version.unwrap();
+    let current = current_version(migrations) as u64;
+    if version == 0 || version > current {
+        return Err(format!("unsupported version {}", version));
+    }
+    for migration in &migrations[(version - 1) as usize..] {
+        migration(&mut value);
+    }
+    value["version"] = Value::from(current);
+    serde_json::from_value(value).map_err(|error| error.to_string())
+}
+
+// Bincode files are only read to import them into the JSON config.
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Option<T> {
+    bincode::deserialize(data).ok()
+}
+
+fn decode_legacy_process_entrys(data: &[u8]) -> Option<Vec<ProcessEntry>> {
+    decode::<Vec<ProcessEntryV0>>(data)
+        .map(|entries| entries.into_iter().map(ProcessEntry::from).collect())
+}
+
+fn decode_legacy_mqtt_settings(data: &[u8]) -> Option<MqttSettings> {
+    decode::<MqttSettingsV0>(data).map(MqttSettings::from)
+}
+
+// Writes go to a temporary file that is renamed over the old one, so a crash never leaves
+// a half written file behind.
+fn write_atomic(path: &Path, data: &str) -> io::Result<()> {
+    let temporary_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temporary_path)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_all()?;
+    }
+    rename(&temporary_path, path)?;
+    sync_directory(path)
+}
+
+// The rename is only durable once the directory entry is on disk. `File::open` cannot open
+// a directory on Windows, so this only happens on unix.
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(directory) => File::open(directory)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
 
 pub struct Storage {
     pub path: Option<PathBuf>,
+    // Messages about files that had to be restored, see `take_recoveries`.
+    recoveries: Vec<String>,
+    // Content of each config file as last read or written here, to tell external edits apart.
+    known: HashMap<PathBuf, String>,
+    // Loaded on first use, saving entries has to keep the other profiles.
+    profiles: Option<Profiles>,
+    // The global broker settings as last read or written.
+    mqtt_settings: Option<MqttSettings>,
 }
 impl Storage {
-    const PROCESS_ENTRYS_PATH: &'static str = "process_entrys.dat";
-    const MQTT_SETTINGS_PATH: &'static str = "mqtt_settings.dat";
+    const PROCESS_ENTRYS_PATH: &'static str = "process_entrys.json";
+    const MQTT_SETTINGS_PATH: &'static str = "mqtt_settings.json";
+    const LOG_SETTINGS_PATH: &'static str = "log_settings.json";
+    const SCAN_SETTINGS_PATH: &'static str = "scan_settings.json";
+    const LEGACY_PROCESS_ENTRYS_PATH: &'static str = "process_entrys.dat";
+    const LEGACY_MQTT_SETTINGS_PATH: &'static str = "mqtt_settings.dat";
+    // Imported bincode files are renamed instead of deleted, so a downgrade can still use them.
+    const IMPORTED_EXTENSION: &'static str = "dat.imported";
+    const BACKUPS_PATH: &'static str = "backups";
+    const MAX_BACKUPS: usize = 10;
+
     pub fn new() -> Self {
-        Self { path: None }
+        Self {
+            path: None,
+            recoveries: vec![],
+            known: HashMap::new(),
+            profiles: None,
+            mqtt_settings: None,
+        }
     }
 
     pub fn set_path(&mut self, path: String) {
@@ -21,103 +280,651 @@ impl Storage {
         if !path.exists() {
             let result = create_dir_all(&path);
             if result.is_err() {
-                log("failed to create directory for storage");
+                error!("failed to create directory for storage");
             }
         }
         self.path = Some(path);
     }
 
-    pub fn save_process_entrys(&self, process_entrys: &[ProcessEntry]) {
-        let serialized = bincode::serialize(process_entrys);
-        if serialized.is_err() {
-            log("failed to serialize process entrys");
-            return;
+    // Saves the entries of the active profile.
+    pub fn save_process_entrys(&mut self, process_entrys: &[ProcessEntry]) -> Result<(), Error> {
+        self.change_profiles(|profiles| {
+            profiles.active_mut().process_entrys = process_entrys.to_vec();
+            Ok(())
+        })
+    }
+
+    // The entries of the active profile.
+    pub fn get_saved_process_entrys(&mut self) -> Vec<ProcessEntry> {
+        self.profiles().active().process_entrys.clone()
+    }
+
+    pub fn get_profiles(&mut self) -> ProfileList {
+        self.profiles().list()
+    }
+
+    // Applies a change to the profiles and writes them, nothing changes if either fails.
+    pub fn change_profiles<T>(
+        &mut self,
+        change: impl FnOnce(&mut Profiles) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.path.is_none() {
+            return Err(Error::NotConfigured("storage path is not set"));
         }
-        let serialized = serialized.unwrap();
+        let mut profiles = self.profiles().clone();
+        let result = change(&mut profiles)?;
+        self.write_profiles(&profiles)?;
+        self.profiles = Some(profiles);
+        Ok(result)
+    }
+
+    fn profiles(&mut self) -> &mut Profiles {
+        if self.profiles.is_none() {
+            let profiles = self.read_profiles();
+            self.profiles = Some(profiles);
+        }
+        self.profiles.as_mut().unwrap()
+    }
+
+    fn read_profiles(&mut self) -> Profiles {
         if self.path.is_none() {
-            return;
+            return Profiles::new(vec![]);
         }
-        let path = self.path.as_ref().unwrap();
-        let result = write(path.join(Self::PROCESS_ENTRYS_PATH), serialized);
-        if result.is_err() {
-            log("failed to write process entrys");
+        let path = self.path.as_ref().unwrap().join(Self::PROCESS_ENTRYS_PATH);
+        if !path.exists() {
+            return Profiles::new(self.import_legacy_process_entrys().unwrap_or_default());
         }
+        self.load::<ValidProfiles>(&path, PROCESS_ENTRYS_MIGRATIONS, "process entrys")
+            .map(|profiles| profiles.0)
+            .unwrap_or_else(|| Profiles::new(vec![]))
     }
 
-    pub fn get_saved_process_entrys(&self) -> Vec<ProcessEntry> {
+    fn write_profiles(&mut self, profiles: &Profiles) -> Result<(), Error> {
         if self.path.is_none() {
-            return vec![];
+            return Err(Error::NotConfigured("storage path is not set"));
         }
         let path = self.path.as_ref().unwrap();
-        if !path.join(Self::PROCESS_ENTRYS_PATH).exists() {
-            return vec![];
+        let serialized = to_json(&StoredProfiles::from(profiles))?;
+        self.save(&path.join(Self::PROCESS_ENTRYS_PATH), &serialized)
+            .map_err(|error| Error::Storage(format!("failed to write process entrys: {}", error)))
+    }
+
+    fn import_legacy_process_entrys(&mut self) -> Option<Vec<ProcessEntry>> {
+        let path = self.path.as_ref()?.join(Self::LEGACY_PROCESS_ENTRYS_PATH);
+        if !path.exists() {
+            return None;
         }
-        let data = read(path.join(Self::PROCESS_ENTRYS_PATH));
+        let data = read(&path);
         if data.is_err() {
-            log("failed to read process entrys");
-            return vec![];
+            error!("failed to read legacy process entrys");
+            return None;
         }
         let data = data.unwrap();
+        let deserialized = decode_legacy_process_entrys(&data);
+        if deserialized.is_none() {
+            error!("failed to deserialize legacy process entrys");
+            return None;
+        }
+        let process_entrys: Vec<ProcessEntry> = deserialized.unwrap();
+        if let Err(error) = self.write_profiles(&Profiles::new(process_entrys.clone())) {
+            error!("{}", error);
+            return Some(process_entrys);
+        }
+        if rename(&path, path.with_extension(Self::IMPORTED_EXTENSION)).is_err() {
+            error!("failed to rename imported process entrys");
+        }
+        Some(process_entrys)
+    }
 
-        let deserialized = bincode::deserialize(&data);
-        if deserialized.is_err() {
-            log("failed to deserialize process entrys");
-            return vec![];
+    // The broker of the active profile, or the global one if it has none.
+    pub fn get_active_mqtt_settings(&mut self) -> MqttSettings {
+        let profile_settings = self.profiles().active().mqtt_settings.clone();
+        if let Some(settings) = profile_settings {
+            return settings;
+        }
+        if let Some(settings) = &self.mqtt_settings {
+            return settings.clone();
         }
-        deserialized.unwrap()
+        self.get_mqtt_settings()
     }
 
-    pub fn get_mqtt_settings(&self) -> MqttSettings {
+    fn get_mqtt_settings(&mut self) -> MqttSettings {
+        let settings = self.read_mqtt_settings();
+        self.mqtt_settings = Some(settings.clone());
+        settings
+    }
+
+    fn read_mqtt_settings(&mut self) -> MqttSettings {
         let default = MqttSettings {
             ip: "localhost".to_string(),
             port: 1883,
+            username: None,
+            password: None,
+            tls: None,
+            availability: None,
         };
         if self.path.is_none() {
             return default;
         }
-        let path = self.path.as_ref().unwrap();
-        if !path.join(Self::MQTT_SETTINGS_PATH).exists() {
-            let serialized = bincode::serialize(&default);
-            if serialized.is_err() {
-                log("failed to serialize mqtt settings");
-                return default;
+        let path = self.path.as_ref().unwrap().join(Self::MQTT_SETTINGS_PATH);
+        if !path.exists() {
+            if let Some(settings) = self.import_legacy_mqtt_settings() {
+                return settings;
             }
-            let serialized = serialized.unwrap();
-
-            let response = write(path.join(Self::MQTT_SETTINGS_PATH), serialized);
-            if response.is_err() {
-                log("failed to write mqtt settings");
+            if let Err(error) = self.save_global_mqtt_settings(&default) {
+                error!("{}", error);
             }
             return default;
         }
-        let data = read(path.join(Self::MQTT_SETTINGS_PATH));
+        self.load::<StoredMqttSettings>(&path, MQTT_SETTINGS_MIGRATIONS, "mqtt settings")
+            .map(MqttSettings::from)
+            .unwrap_or(default)
+    }
+
+    fn import_legacy_mqtt_settings(&mut self) -> Option<MqttSettings> {
+        let path = self.path.as_ref()?.join(Self::LEGACY_MQTT_SETTINGS_PATH);
+        if !path.exists() {
+            return None;
+        }
+        let data = read(&path);
         if data.is_err() {
-            log("failed to read mqtt settings");
-            return default;
+            error!("failed to read legacy mqtt settings");
+            return None;
         }
         let data = data.unwrap();
-        let deserialized = bincode::deserialize(&data);
-        if deserialized.is_err() {
-            log("failed to deserialize mqtt settings");
-            return default;
+        let deserialized = decode_legacy_mqtt_settings(&data);
+        if deserialized.is_none() {
+            error!("failed to deserialize legacy mqtt settings");
+            return None;
         }
-        deserialized.unwrap()
+        let settings = deserialized.unwrap();
+        if let Err(error) = self.save_global_mqtt_settings(&settings) {
+            error!("{}", error);
+            return Some(settings);
+        }
+        if rename(&path, path.with_extension(Self::IMPORTED_EXTENSION)).is_err() {
+            error!("failed to rename imported mqtt settings");
+        }
+        Some(settings)
     }
 
-    pub fn save_mqtt_settings(&self, settings: &MqttSettings) {
+    // Saves to the broker of the active profile if it has one.
+    pub fn save_mqtt_settings(&mut self, settings: &MqttSettings) -> Result<(), Error> {
+        if self.profiles().active().mqtt_settings.is_some() {
+            return self.change_profiles(|profiles| {
+                profiles.active_mut().mqtt_settings = Some(settings.clone());
+                Ok(())
+            });
+        }
+        self.save_global_mqtt_settings(settings)
+    }
+
+    fn save_global_mqtt_settings(&mut self, settings: &MqttSettings) -> Result<(), Error> {
         if self.path.is_none() {
-            return;
+            return Err(Error::NotConfigured("storage path is not set"));
+        }
+        let path = self.path.as_ref().unwrap();
+        let serialized = to_json(&StoredMqttSettings::from(settings))?;
+        self.save(&path.join(Self::MQTT_SETTINGS_PATH), &serialized)
+            .map_err(|error| Error::Storage(format!("failed to write mqtt settings: {}", error)))?;
+        self.mqtt_settings = Some(settings.clone());
+        Ok(())
+    }
+
+    // Defaults are used without writing them, the file only exists once they were changed.
+    pub fn get_log_settings(&mut self) -> LogSettings {
+        if self.path.is_none() {
+            return LogSettings::new();
+        }
+        let path = self.path.as_ref().unwrap().join(Self::LOG_SETTINGS_PATH);
+        if !path.exists() {
+            return LogSettings::new();
+        }
+        let settings = self
+            .load::<StoredLogSettings>(&path, LOG_SETTINGS_MIGRATIONS, "log settings")
+            .map(|stored| stored.settings)
+            .unwrap_or_else(LogSettings::new);
+        if let Err(error) = settings.validate() {
+            warn!("ignored invalid log settings: {}", error);
+            return LogSettings::new();
+        }
+        settings
+    }
+
+    pub fn save_log_settings(&mut self, settings: &LogSettings) -> Result<(), Error> {
+        if self.path.is_none() {
+            return Err(Error::NotConfigured("storage path is not set"));
+        }
+        let path = self.path.as_ref().unwrap();
+        let serialized = to_json(&StoredLogSettings {
+            version: current_version(LOG_SETTINGS_MIGRATIONS),
+            settings: settings.clone(),
+        })?;
+        self.save(&path.join(Self::LOG_SETTINGS_PATH), &serialized)
+            .map_err(|error| Error::Storage(format!("failed to write log settings: {}", error)))
+    }
+
+    // Like the log settings, only written once changed.
+    pub fn get_scan_settings(&mut self) -> ScanSettings {
+        if self.path.is_none() {
+            return ScanSettings::new();
+        }
+        let path = self.path.as_ref().unwrap().join(Self::SCAN_SETTINGS_PATH);
+        if !path.exists() {
+            return ScanSettings::new();
+        }
+        let settings = self
+            .load::<StoredScanSettings>(&path, SCAN_SETTINGS_MIGRATIONS, "scan settings")
+            .map(|stored| stored.settings)
+            .unwrap_or_else(ScanSettings::new);
+        if let Err(error) = settings.validate() {
+            warn!("ignored invalid scan settings: {}", error);
+            return ScanSettings::new();
+        }
+        settings
+    }
+
+    pub fn save_scan_settings(&mut self, settings: &ScanSettings) -> Result<(), Error> {
+        if self.path.is_none() {
+            return Err(Error::NotConfigured("storage path is not set"));
         }
         let path = self.path.as_ref().unwrap();
-        let serialized = bincode::serialize(settings);
-        if serialized.is_err() {
-            log("failed to serialize mqtt settings");
+        let serialized = to_json(&StoredScanSettings {
+            version: current_version(SCAN_SETTINGS_MIGRATIONS),
+            settings: settings.clone(),
+        })?;
+        self.save(&path.join(Self::SCAN_SETTINGS_PATH), &serialized)
+            .map_err(|error| Error::Storage(format!("failed to write scan settings: {}", error)))
+    }
+
+    // Profiles from a file edited by something else since it was last read or written here.
+    // The file is left alone when it is invalid, it may be in the middle of being edited.
+    pub fn reload_profiles(&mut self) -> Option<Result<ProfileList, String>> {
+        let data = self.external_change(Self::PROCESS_ENTRYS_PATH)?;
+        let profiles = from_json::<ValidProfiles>(&data, PROCESS_ENTRYS_MIGRATIONS);
+        if let Err(error) = profiles {
+            return Some(Err(error));
+        }
+        let profiles = profiles.unwrap().0;
+        if let Err(error) = profiles.validate_entries() {
+            return Some(Err(error));
+        }
+        let list = profiles.list();
+        self.profiles = Some(profiles);
+        Some(Ok(list))
+    }
+
+    // The global broker settings, only reported when they changed.
+    pub fn reload_mqtt_settings(&mut self) -> Option<Result<MqttSettings, String>> {
+        let data = self.external_change(Self::MQTT_SETTINGS_PATH)?;
+        let settings = from_json::<StoredMqttSettings>(&data, MQTT_SETTINGS_MIGRATIONS)
+            .map(MqttSettings::from);
+        if let Ok(settings) = &settings {
+            self.mqtt_settings = Some(settings.clone());
+        }
+        Some(settings)
+    }
+
+    pub fn reload_log_settings(&mut self) -> Option<Result<LogSettings, String>> {
+        let data = self.external_change(Self::LOG_SETTINGS_PATH)?;
+        Some(
+            from_json::<StoredLogSettings>(&data, LOG_SETTINGS_MIGRATIONS)
+                .map(|stored| stored.settings)
+                .and_then(|settings| settings.validate().map(|_| settings)),
+        )
+    }
+
+    pub fn reload_scan_settings(&mut self) -> Option<Result<ScanSettings, String>> {
+        let data = self.external_change(Self::SCAN_SETTINGS_PATH)?;
+        Some(
+            from_json::<StoredScanSettings>(&data, SCAN_SETTINGS_MIGRATIONS)
+                .map(|stored| stored.settings)
+                .and_then(|settings| settings.validate().map(|_| settings)),
+        )
+    }
+
+    fn external_change(&mut self, file: &str) -> Option<String> {
+        let path = self.path.as_ref()?.join(file);
+        let data = read_to_string(&path).ok()?;
+        if self.known.get(&path) == Some(&data) {
+            return None;
+        }
+        self.known.insert(path, data.clone());
+        Some(data)
+    }
+
+    // Messages about files that could not be read since the last call, to show to the user.
+    pub fn take_recoveries(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.recoveries)
+    }
+
+    // Reads a JSON config file. A file that can not be read is moved into the backups
+    // directory and replaced by the newest backup that can.
+    fn load<T: DeserializeOwned>(
+        &mut self,
+        path: &Path,
+        migrations: &[Migration],
+        what: &str,
+    ) -> Option<T> {
+        let loaded = read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|data| from_json::<T>(&data, migrations).map(|value| (data, value)));
+        let error = match loaded {
+            Ok((data, value)) => {
+                self.known.insert(path.to_path_buf(), data);
+                return Some(value);
+            }
+            Err(error) => error,
+        };
+        error!("failed to load {}: {}", what, error);
+
+        let damaged_path = self.backup_path(path, "damaged.json");
+        if damaged_path.is_none() || rename(path, damaged_path.as_ref().unwrap()).is_err() {
+            error!("failed to move damaged {} aside", what);
+        }
+        self.prune_backups(path, true);
+        for backup in self.backups(path, false) {
+            let data = read_to_string(&backup);
+            if data.is_err() {
+                continue;
+            }
+            let data = data.unwrap();
+            let value = from_json::<T>(&data, migrations);
+            if value.is_err() {
+                continue;
+            }
+            if write_atomic(path, &data).is_err() {
+                error!("failed to restore {} from backup", what);
+            }
+            self.known.insert(path.to_path_buf(), data);
+            self.recover(format!(
+                "The stored {} could not be read and were restored from the backup {}.",
+                what,
+                backup.display()
+            ));
+            return value.ok();
+        }
+        self.recover(format!(
+            "The stored {} could not be read and no valid backup was found, the damaged file was kept in the {} directory.",
+            what,
+            Self::BACKUPS_PATH
+        ));
+        None
+    }
+
+    fn recover(&mut self, message: String) {
+        warn!("{}", message);
+        self.recoveries.push(message);
+    }
+
+    // Keeps the previous content as a timestamped backup before replacing it.
+    fn save(&mut self, path: &Path, data: &str) -> io::Result<()> {
+        let current = read_to_string(path);
+        if current.as_deref().is_ok_and(|current| current == data) {
+            return Ok(());
+        }
+        if current.is_ok() {
+            self.backup(path);
+        }
+        write_atomic(path, data)?;
+        self.known.insert(path.to_path_buf(), data.to_string());
+        Ok(())
+    }
+
+    fn backup(&self, path: &Path) {
+        let backup_path = self.backup_path(path, "json");
+        if backup_path.is_none() {
+            return;
+        }
+        if copy(path, backup_path.unwrap()).is_err() {
+            error!("failed to back up config file");
             return;
         }
-        let serialized = serialized.unwrap();
-        let result = write(path.join(Self::MQTT_SETTINGS_PATH), serialized);
-        if result.is_err() {
-            log("failed to write mqtt settings");
+        self.prune_backups(path, false);
+    }
+
+    // Keeps the newest `MAX_BACKUPS` backups, or damaged copies, of the given file.
+    fn prune_backups(&self, path: &Path, damaged: bool) {
+        for old_backup in self
+            .backups(path, damaged)
+            .into_iter()
+            .skip(Self::MAX_BACKUPS)
+        {
+            if remove_file(old_backup).is_err() {
+                error!("failed to remove old backup");
+            }
+        }
+    }
+
+    // `backups/<file stem>-<timestamp>Z.<extension>`, the UTC timestamp sorts chronologically
+    // even when the local clock jumps back at the end of daylight saving time.
+    // Saves within the same millisecond get a counter, `_001` sorts after the plain name.
+    fn backup_path(&self, path: &Path, extension: &str) -> Option<PathBuf> {
+        let backups_path = self.path.as_ref()?.join(Self::BACKUPS_PATH);
+        if !backups_path.exists() && create_dir_all(&backups_path).is_err() {
+            error!("failed to create backups directory");
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        let timestamp = Utc::now().format("%Y%m%dT%H%M%S%3fZ");
+        let mut backup_path = backups_path.join(format!("{}-{}.{}", stem, timestamp, extension));
+        let mut counter = 0;
+        while backup_path.exists() {
+            counter += 1;
+            backup_path = backups_path.join(format!(
+                "{}-{}_{:03}.{}",
+                stem, timestamp, counter, extension
+            ));
+        }
+        Some(backup_path)
+    }
+
+    // Backups of the given file, or its damaged copies, newest first.
+    fn backups(&self, path: &Path, damaged: bool) -> Vec<PathBuf> {
+        let backups_path = self.path.as_ref().map(|path| path.join(Self::BACKUPS_PATH));
+        let stem = path.file_stem().and_then(|stem| stem.to_str());
+        if backups_path.is_none() || stem.is_none() {
+            return vec![];
+        }
+        let prefix = format!("{}-", stem.unwrap());
+        let entries = read_dir(backups_path.unwrap());
+        if entries.is_err() {
+            return vec![];
+        }
+        let mut backups: Vec<PathBuf> = entries
+            .unwrap()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.starts_with(&prefix)
+                            && name.ends_with(".json")
+                            && name.ends_with(".damaged.json") == damaged
+                    })
+            })
+            .collect();
+        backups.sort();
+        backups.reverse();
+        backups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        matcher::{MatchMode, MatchTarget},
+        process_entry::{new_entry_id, ProcessAction, DEFAULT_QOS, DEFAULT_RETAIN},
+    };
+
+    fn action(topic: &str, value: &str, off_value: &str) -> ProcessAction {
+        ProcessAction {
+            topic: topic.to_string(),
+            value: value.to_string(),
+            off_value: off_value.to_string(),
+            qos: DEFAULT_QOS,
+            retain: DEFAULT_RETAIN,
+        }
+    }
+
+    #[test]
+    fn decodes_legacy_process_entrys() {
+        let data = bincode::serialize(&vec![ProcessEntryV0 {
+            is_running: true,
+            name: "game.exe".to_string(),
+            topic: "light/color".to_string(),
+            value: "red".to_string(),
+            off_value: "off".to_string(),
+        }])
+        .unwrap();
+        let entries = decode_legacy_process_entrys(&data).unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert!(!entry.id.is_empty());
+        assert!(!entry.is_running);
+        assert_eq!(entry.name, "game.exe");
+        assert_eq!(entry.match_mode, MatchMode::Exact);
+        assert_eq!(entry.match_target, MatchTarget::Name);
+        assert_eq!(entry.priority, 0);
+        assert_eq!((entry.on_delay, entry.off_delay), (0, 0));
+        // Every start also set the brightness before entries had several actions.
+        assert_eq!(
+            entry.actions,
+            vec![
+                action("light/color", "red", "off"),
+                action("tgn/esp_3/neopixel/brightness", "150", "150")
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_legacy_mqtt_settings() {
+        let data = bincode::serialize(&MqttSettingsV0 {
+            ip: "broker".to_string(),
+            port: 1883,
+        })
+        .unwrap();
+        let settings = decode_legacy_mqtt_settings(&data).unwrap();
+        assert_eq!(settings.ip, "broker");
+        assert_eq!(settings.port, 1883);
+        assert_eq!(settings.username, None);
+        assert_eq!(settings.password, None);
+        assert_eq!(settings.tls, None);
+        assert_eq!(settings.availability, None);
+    }
+
+    #[test]
+    fn rejects_damaged_legacy_files() {
+        assert_eq!(decode_legacy_process_entrys(&[0xff; 4]), None);
+        assert_eq!(decode_legacy_mqtt_settings(&[0x01]), None);
+    }
+
+    #[test]
+    fn does_not_store_running_state() {
+        let mut entry = ProcessEntry::new();
+        entry.is_running = true;
+        let data = to_json(&StoredProfiles::from(&Profiles::new(vec![entry]))).unwrap();
+        assert!(!data.contains("is_running"));
+        let profiles = from_json::<ValidProfiles>(&data, PROCESS_ENTRYS_MIGRATIONS)
+            .unwrap()
+            .0;
+        assert!(!profiles.active().process_entrys[0].is_running);
+    }
+
+    #[test]
+    fn migrates_entries_into_default_profile() {
+        let entry = ProcessEntry::new();
+        let data = json!({ "version": 1, "entries": [entry] }).to_string();
+        let profiles = from_json::<ValidProfiles>(&data, PROCESS_ENTRYS_MIGRATIONS)
+            .unwrap()
+            .0;
+        assert_eq!(profiles.profiles().len(), 1);
+        assert_eq!(profiles.active().name, DEFAULT_PROFILE);
+        assert_eq!(profiles.active().process_entrys, vec![entry]);
+        assert_eq!(profiles.active().mqtt_settings, None);
+    }
+
+    #[test]
+    fn reads_current_version_without_migrating() {
+        let profiles = Profiles::new(vec![ProcessEntry::new()]);
+        let data = to_json(&StoredProfiles::from(&profiles)).unwrap();
+        let read = from_json::<ValidProfiles>(&data, PROCESS_ENTRYS_MIGRATIONS)
+            .unwrap()
+            .0;
+        assert_eq!(read.list(), profiles.list());
+        assert_eq!(
+            read.active().process_entrys,
+            profiles.active().process_entrys
+        );
+    }
+
+    #[test]
+    fn rejects_version_zero() {
+        let data = json!({ "version": 0, "entries": [] }).to_string();
+        assert!(from_json::<ValidProfiles>(&data, PROCESS_ENTRYS_MIGRATIONS).is_err());
+    }
+
+    #[test]
+    fn rejects_future_version() {
+        let version = current_version(PROCESS_ENTRYS_MIGRATIONS) + 1;
+        let data = json!({ "version": version, "active_profile": DEFAULT_PROFILE, "profiles": [] })
+            .to_string();
+        assert!(from_json::<ValidProfiles>(&data, PROCESS_ENTRYS_MIGRATIONS).is_err());
+    }
+
+    #[test]
+    fn rejects_missing_version() {
+        let data = json!({ "entries": [] }).to_string();
+        assert!(from_json::<ValidProfiles>(&data, PROCESS_ENTRYS_MIGRATIONS).is_err());
+    }
+
+    fn temp_storage() -> (Storage, PathBuf) {
+        let path = std::env::temp_dir().join(format!("process-color-{}", new_entry_id()));
+        let mut storage = Storage::new();
+        storage.set_path(path.to_str().unwrap().to_string());
+        (storage, path)
+    }
+
+    #[test]
+    fn backup_path_does_not_overwrite_existing_backup() {
+        let (storage, path) = temp_storage();
+        let file = path.join(Storage::PROCESS_ENTRYS_PATH);
+        let first = storage.backup_path(&file, "json").unwrap();
+        File::create(&first).unwrap();
+        let second = storage.backup_path(&file, "json").unwrap();
+        assert_ne!(first, second);
+        File::create(&second).unwrap();
+        assert_eq!(storage.backups(&file, false), vec![second, first]);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn write_atomic_replaces_file_without_leftovers() {
+        let (_, path) = temp_storage();
+        create_dir_all(&path).unwrap();
+        let file = path.join(Storage::PROCESS_ENTRYS_PATH);
+        write_atomic(&file, "first").unwrap();
+        write_atomic(&file, "second").unwrap();
+        assert_eq!(read_to_string(&file).unwrap(), "second");
+        assert!(!file.with_extension("tmp").exists());
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn prunes_backups_and_damaged_copies_separately() {
+        let (mut storage, path) = temp_storage();
+        let file = path.join(Storage::PROCESS_ENTRYS_PATH);
+        for index in 0..Storage::MAX_BACKUPS + 2 {
+            storage.save(&file, &index.to_string()).unwrap();
+            let damaged = storage.backup_path(&file, "damaged.json").unwrap();
+            File::create(damaged).unwrap();
         }
+        storage.prune_backups(&file, true);
+        assert_eq!(storage.backups(&file, false).len(), Storage::MAX_BACKUPS);
+        assert_eq!(storage.backups(&file, true).len(), Storage::MAX_BACKUPS);
+        let newest = read_to_string(&storage.backups(&file, false)[0]).unwrap();
+        assert_eq!(newest, Storage::MAX_BACKUPS.to_string());
+        std::fs::remove_dir_all(path).unwrap();
     }
 }
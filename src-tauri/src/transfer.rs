@@ -0,0 +1,399 @@
+use std::{collections::BTreeSet, fs};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    matcher::{MatchMode, MatchTarget},
+    mqtt_settings::{AvailabilitySettings, MqttSettings, TlsSettings},
+    process_entry::{validate_entry, ProcessAction, ProcessEntry},
+};
+
+// Files for moving entries between machines, independent of the storage format:
+//
+// {
+//   "format": "process-color",
+//   "version": 1,
+//   "entries": [
+//     {
+//       "name": "obs64.exe",
+//       "match_mode": "exact",          exact, case_insensitive, glob, regex or substring
+//       "match_target": "name",         name, exe_path or command_line
+//       "priority": 0,
+//       "on_delay": 0,                  seconds
+//       "off_delay": 0,                 seconds
+//       "actions": [
+//         { "topic": "light/desk", "value": "red", "off_value": "off", "qos": 1, "retain": true }
+//       ]
+//     }
+//   ],
+//   "mqtt_settings": {                  optional
+//     "ip": "localhost",
+//     "port": 1883,
+//     "username": null,
+//     "tls": null,                      or { ca_file, client_cert_file, client_key_file, verify_hostname }
+//     "availability": null              or { topic, online_payload, offline_payload }
+//   }
+// }
+//
+// Entry ids and running state are not exported, imported entries get new ids. The broker
+// password is never exported.
+const FORMAT: &str = "process-color";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct TransferFile {
+    format: String,
+    version: u32,
+    entries: Vec<TransferEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mqtt_settings: Option<TransferMqttSettings>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransferEntry {
+    name: String,
+    match_mode: MatchMode,
+    match_target: MatchTarget,
+    priority: i32,
+    on_delay: u64,
+    off_delay: u64,
+    actions: Vec<ProcessAction>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransferMqttSettings {
+    ip: String,
+    port: u16,
+    username: Option<String>,
+    tls: Option<TlsSettings>,
+    availability: Option<AvailabilitySettings>,
+}
+
+impl From<&ProcessEntry> for TransferEntry {
+    fn from(entry: &ProcessEntry) -> Self {
+        Self {
+            name: entry.name.clone(),
+            match_mode: entry.match_mode,
+            match_target: entry.match_target,
+            priority: entry.priority,
+            on_delay: entry.on_delay,
+            off_delay: entry.off_delay,
+            actions: entry.actions.clone(),
+        }
+    }
+}
+
+impl From<TransferEntry> for ProcessEntry {
+    fn from(entry: TransferEntry) -> Self {
+        let mut process_entry = ProcessEntry::new();
+        process_entry.name = entry.name;
+        process_entry.match_mode = entry.match_mode;
+        process_entry.match_target = entry.match_target;
+        process_entry.priority = entry.priority;
+        process_entry.on_delay = entry.on_delay;
+        process_entry.off_delay = entry.off_delay;
+        process_entry.actions = entry.actions;
+        process_entry
+    }
+}
+
+impl From<&MqttSettings> for TransferMqttSettings {
+    fn from(settings: &MqttSettings) -> Self {
+        Self {
+            ip: settings.ip.clone(),
+            port: settings.port,
+            username: settings.username.clone(),
+            tls: settings.tls.clone(),
+            availability: settings.availability.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    // Adds the entries that are not already there.
+    Merge,
+    // Replaces all entries of the active profile.
+    Replace,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub added: usize,
+    // Names of the imported entries that were skipped as duplicates.
+    pub skipped: Vec<String>,
+    pub mqtt_settings: bool,
+}
+
+// What a file contributes to the current config, see `import`.
+pub struct Import {
+    pub process_entrys: Vec<ProcessEntry>,
+    pub mqtt_settings: Option<MqttSettings>,
+    pub summary: ImportSummary,
+}
+
+pub fn export(
+    path: &str,
+    process_entrys: &[ProcessEntry],
+    mqtt_settings: Option<&MqttSettings>,
+) -> Result<(), Error> {
+    let file = TransferFile {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        entries: process_entrys.iter().map(TransferEntry::from).collect(),
+        mqtt_settings: mqtt_settings.map(TransferMqttSettings::from),
+    };
+    let data = serde_json::to_string_pretty(&file)
+        .map_err(|error| Error::Transfer(format!("failed to serialize export: {}", error)))?;
+    fs::write(path, data)
+        .map_err(|error| Error::Transfer(format!("failed to write {}: {}", path, error)))
+}
+
+// Reads a file written by `export` and combines it with the current entries and settings.
+// Broker settings are only taken when asked for, the current password is only kept for the
+// same broker and username.
+pub fn import(
+    path: &str,
+    mode: ImportMode,
+    with_mqtt_settings: bool,
+    current_entrys: &[ProcessEntry],
+    current_mqtt_settings: Option<&MqttSettings>,
+) -> Result<Import, Error> {
+    let data = fs::read_to_string(path)
+        .map_err(|error| Error::Transfer(format!("failed to read {}: {}", path, error)))?;
+    let file: TransferFile = serde_json::from_str(&data)
+        .map_err(|error| Error::Transfer(format!("invalid import file: {}", error)))?;
+    if file.format != FORMAT {
+        return Err(Error::Transfer(format!(
+            "unknown import format \"{}\"",
+            file.format
+        )));
+    }
+    if file.version == 0 || file.version > VERSION {
+        return Err(Error::Transfer(format!(
+            "unsupported import version {}",
+            file.version
+        )));
+    }
+
+    let mut process_entrys = match mode {
+        ImportMode::Merge => current_entrys.to_vec(),
+        ImportMode::Replace => vec![],
+    };
+    let mut summary = ImportSummary {
+        added: 0,
+        skipped: vec![],
+        mqtt_settings: false,
+    };
+    for entry in file.entries.into_iter().map(ProcessEntry::from) {
+        validate_entry(&entry.name, entry.match_mode, &entry.actions)
+            .map_err(|error| Error::Transfer(format!("entry \"{}\": {}", entry.name, error)))?;
+        if process_entrys
+            .iter()
+            .any(|existing| is_duplicate(existing, &entry))
+        {
+            summary.skipped.push(entry.name);
+            continue;
+        }
+        process_entrys.push(entry);
+        summary.added += 1;
+    }
+
+    let mut mqtt_settings = None;
+    if with_mqtt_settings {
+        if file.mqtt_settings.is_none() {
+            return Err(Error::Transfer(
+                "the import file contains no mqtt settings".to_string(),
+            ));
+        }
+        let imported = file.mqtt_settings.unwrap();
+        // The password must not be sent to a different broker or account.
+        let password = current_mqtt_settings
+            .filter(|current| {
+                current.ip == imported.ip
+                    && current.port == imported.port
+                    && current.username == imported.username
+            })
+            .and_then(|current| current.password.clone());
+        mqtt_settings = Some(MqttSettings {
+            ip: imported.ip,
+            port: imported.port,
+            username: imported.username,
+            password,
+            tls: imported.tls,
+            availability: imported.availability,
+        });
+        summary.mqtt_settings = true;
+    }
+    Ok(Import {
+        process_entrys,
+        mqtt_settings,
+        summary,
+    })
+}
+
+// Entries watching the same process name and publishing to the same topics.
+fn is_duplicate(a: &ProcessEntry, b: &ProcessEntry) -> bool {
+    a.name == b.name && topics(a) == topics(b)
+}
+
+fn topics(entry: &ProcessEntry) -> BTreeSet<&str> {
+    entry
+        .actions
+        .iter()
+        .map(|action| action.topic.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::process_entry::new_entry_id;
+
+    fn temp_file() -> PathBuf {
+        std::env::temp_dir().join(format!("process-color-{}.json", new_entry_id()))
+    }
+
+    fn entry(name: &str, topic: &str) -> ProcessEntry {
+        let mut entry = ProcessEntry::new();
+        entry.name = name.to_string();
+        entry.match_mode = MatchMode::Glob;
+        entry.match_target = MatchTarget::ExePath;
+        entry.priority = 2;
+        entry.on_delay = 3;
+        entry.off_delay = 4;
+        entry.actions[0].topic = topic.to_string();
+        entry.actions[0].value = "on".to_string();
+        entry.actions[0].off_value = "off".to_string();
+        entry
+    }
+
+    fn settings(ip: &str, port: u16, username: Option<&str>) -> MqttSettings {
+        MqttSettings {
+            ip: ip.to_string(),
+            port,
+            username: username.map(str::to_string),
+            password: Some("secret".to_string()),
+            tls: None,
+            availability: Some(AvailabilitySettings {
+                topic: "process-color/status".to_string(),
+                online_payload: "online".to_string(),
+                offline_payload: "offline".to_string(),
+            }),
+        }
+    }
+
+    fn import_settings(exported: &MqttSettings, current: &MqttSettings) -> Option<String> {
+        let path = temp_file();
+        let path = path.to_str().unwrap();
+        export(path, &[], Some(exported)).unwrap();
+        let import = import(path, ImportMode::Merge, true, &[], Some(current)).unwrap();
+        fs::remove_file(path).unwrap();
+        import.mqtt_settings.unwrap().password
+    }
+
+    #[test]
+    fn round_trips_entries_and_settings() {
+        let path = temp_file();
+        let path = path.to_str().unwrap();
+        let entries = vec![
+            entry("game*", "light/desk"),
+            entry("obs64.exe", "light/wall"),
+        ];
+        let settings = settings("broker", 1883, Some("user"));
+        export(path, &entries, Some(&settings)).unwrap();
+        let exported = fs::read_to_string(path).unwrap();
+        assert!(!exported.contains("secret"));
+
+        let import = import(path, ImportMode::Replace, true, &[entry("old", "t")], None).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(import.summary.added, 2);
+        assert!(import.summary.skipped.is_empty());
+        assert!(import.summary.mqtt_settings);
+        assert_eq!(import.process_entrys.len(), 2);
+        for (imported, original) in import.process_entrys.iter().zip(&entries) {
+            assert_ne!(imported.id, original.id);
+            let mut imported = imported.clone();
+            imported.id = original.id.clone();
+            assert_eq!(&imported, original);
+        }
+        let imported_settings = import.mqtt_settings.unwrap();
+        assert_eq!(imported_settings.password, None);
+        assert_eq!(
+            imported_settings,
+            MqttSettings {
+                password: None,
+                ..settings
+            }
+        );
+    }
+
+    #[test]
+    fn merge_skips_duplicates() {
+        let path = temp_file();
+        let path = path.to_str().unwrap();
+        export(path, &[entry("game", "light"), entry("obs", "light")], None).unwrap();
+        let current = vec![entry("game", "light")];
+        let import = import(path, ImportMode::Merge, false, &current, None).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(import.summary.added, 1);
+        assert_eq!(import.summary.skipped, vec!["game".to_string()]);
+        assert_eq!(import.process_entrys.len(), 2);
+        assert_eq!(import.mqtt_settings, None);
+    }
+
+    #[test]
+    fn keeps_password_only_for_same_broker_and_username() {
+        let exported = settings("broker", 1883, Some("user"));
+        assert_eq!(
+            import_settings(&exported, &settings("broker", 1883, Some("user"))).as_deref(),
+            Some("secret")
+        );
+        assert_eq!(
+            import_settings(&exported, &settings("other", 1883, Some("user"))),
+            None
+        );
+        assert_eq!(
+            import_settings(&exported, &settings("broker", 8883, Some("user"))),
+            None
+        );
+        assert_eq!(
+            import_settings(&exported, &settings("broker", 1883, Some("admin"))),
+            None
+        );
+        assert_eq!(
+            import_settings(&exported, &settings("broker", 1883, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        for version in [0, VERSION + 1] {
+            let path = temp_file();
+            let path = path.to_str().unwrap();
+            let file = json!({ "format": FORMAT, "version": version, "entries": [] });
+            fs::write(path, file.to_string()).unwrap();
+            let result = import(path, ImportMode::Merge, false, &[], None);
+            fs::remove_file(path).unwrap();
+            assert!(result.is_err(), "version {} was accepted", version);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        let path = temp_file();
+        let path = path.to_str().unwrap();
+        let file = json!({ "format": "other", "version": VERSION, "entries": [] });
+        fs::write(path, file.to_string()).unwrap();
+        let result = import(path, ImportMode::Merge, false, &[], None);
+        fs::remove_file(path).unwrap();
+        assert!(result.is_err());
+    }
+}
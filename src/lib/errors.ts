@@ -0,0 +1,14 @@
+import type { CommandError } from "./types";
+
+const isCommandError = (error: unknown): error is CommandError =>
+    typeof error === "object" &&
+    error !== null &&
+    "code" in error &&
+    "message" in error;
+
+export const errorMessage = (error: unknown): string =>
+    isCommandError(error) ? error.message : String(error);
+
+export const showError = (error: unknown) => {
+    alert(errorMessage(error));
+};
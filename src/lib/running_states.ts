@@ -1,13 +1,17 @@
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { writable } from "svelte/store";
+import type { RunningState } from "./types";
 
-export const runningStates = writable<boolean[]>([]);
+export const runningStates = writable<Record<string, RunningState>>({});
 let listener: UnlistenFn | null = null;
 
 export const initRunningStates = async () => {
-    listener = await listen<boolean[]>("running_states", (event) => {
-        runningStates.set(event.payload);
-    });
+    listener = await listen<Record<string, RunningState>>(
+        "running_states",
+        (event) => {
+            runningStates.set(event.payload);
+        },
+    );
 };
 
 export const stopRunningStates = () => {
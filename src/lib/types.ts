@@ -1,16 +1,87 @@
-export type ProcessEntry = {
-    name: string;
+export type MatchMode =
+    | "exact"
+    | "case_insensitive"
+    | "glob"
+    | "regex"
+    | "substring";
+
+export type MatchTarget = "name" | "exe_path" | "command_line";
+
+export type RunningState = "stopped" | "starting" | "running" | "stopping";
+
+export type ProcessAction = {
     topic: string;
     value: string;
     off_value: string;
+    qos: number;
+    retain: boolean;
+};
 
+export type ProcessEntry = {
+    id: string;
+    name: string;
+    match_mode: MatchMode;
+    match_target: MatchTarget;
+    priority: number;
+    on_delay: number;
+    off_delay: number;
+    actions: ProcessAction[];
 };
 
-export type ProcessEntryWithIndex = {
-    index: number;
+export type ProcessEntryWithId = {
+    id: string;
     name: string;
-    topic: string;
-    value: string;
-    offValue: string;
+    matchMode: MatchMode;
+    matchTarget: MatchTarget;
+    priority: number;
+    onDelay: number;
+    offDelay: number;
+    actions: ProcessAction[];
     deleteEntry: () => void;
-};
\ No newline at end of file
+};
+export type ProcessInfo = {
+    pid: number;
+    name: string;
+    exe: string | null;
+    cmd: string;
+    user: string | null;
+    start_time: number;
+    cpu_usage: number;
+    memory: number;
+};
+
+export type CommandError = {
+    code: string;
+    message: string;
+};
+
+export type ImportSummary = {
+    added: number;
+    skipped: string[];
+    mqtt_settings: boolean;
+};
+
+export type ProfileInfo = {
+    name: string;
+    own_broker: boolean;
+};
+
+export type ProfileList = {
+    active: string;
+    profiles: ProfileInfo[];
+};
+
+export type LogLevel = "error" | "warn" | "info" | "debug" | "trace";
+
+export type LogSettings = {
+    level: LogLevel;
+    max_file_size: number;
+    max_files: number;
+};
+
+export type ScanSettings = {
+    scan_interval_ms: number;
+    evaluation_interval_ms: number;
+    connection_state_interval_ms: number;
+    adaptive: boolean;
+};